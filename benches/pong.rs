@@ -0,0 +1,46 @@
+//! `cargo bench` runs Pong without input for a fixed number of instructions and prints the speed
+use n2t_lib::cpu::{str2ml, HackCpu, Key, Keyboard};
+use std::fs::read_to_string;
+use std::time::{Duration, Instant};
+
+const CYCLES: usize = 50_000_000;
+
+fn report(name: &str, cycles: usize, elapsed: Duration) {
+    println!(
+        "{:<24} {:>11} instructions in {:>8.3?}  {:>8.1} MIPS",
+        name,
+        cycles,
+        elapsed,
+        cycles as f64 / elapsed.as_secs_f64() / 1e6
+    );
+}
+
+fn bench(name: &str, mut cpu: HackCpu, cycles: usize) {
+    let start = Instant::now();
+    cpu.run(cycles);
+    report(name, cpu.cycles(), start.elapsed());
+}
+
+fn main() {
+    let hack = read_to_string("tests/projects/06/pong/Pong.hack").unwrap();
+    let ml = str2ml(&hack).unwrap();
+
+    let start = Instant::now();
+    let cpu = HackCpu::from_ml(ml.clone()).unwrap();
+    println!("decoding {} words took {:?}", ml.len(), start.elapsed());
+
+    bench("pong", cpu.clone(), CYCLES);
+
+    let mut keyboard = Keyboard::new();
+    for i in 0..100 {
+        let key = if i % 2 == 0 { Key::Left } else { Key::Right };
+        keyboard.tap(i * CYCLES / 100, key, CYCLES / 200);
+    }
+    let mut with_keyboard = cpu.clone();
+    with_keyboard.set_keyboard(keyboard);
+    bench("pong + keyboard", with_keyboard, CYCLES);
+
+    let mut traced = cpu;
+    traced.enable_trace(1024);
+    bench("pong + trace", traced, CYCLES / 10);
+}
@@ -0,0 +1,14 @@
+/// the alu chip from project 02, `zr` and `ng` can be derived from the output
+#[allow(clippy::too_many_arguments)]
+pub fn alu(x: i16, y: i16, zx: bool, nx: bool, zy: bool, ny: bool, f: bool, no: bool) -> i16 {
+    let x = if zx { 0 } else { x };
+    let x = if nx { !x } else { x };
+    let y = if zy { 0 } else { y };
+    let y = if ny { !y } else { y };
+    let out = if f { x.wrapping_add(y) } else { x & y };
+    if no {
+        !out
+    } else {
+        out
+    }
+}
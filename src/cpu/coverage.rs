@@ -0,0 +1,246 @@
+use super::{AsmProgram, CPUInstruction, Jump};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// which instructions ran and which way every conditional jump went, collected by
+/// [`crate::cpu::HackCpu::enable_coverage`]. it keeps counting across resets, so one coverage can
+/// collect all the cases of a test script
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coverage {
+    hits: Vec<usize>,
+    /// `(taken, not taken)` for every conditional jump, `None` for other instructions
+    branches: Vec<Option<(usize, usize)>>,
+}
+
+/// the coverage of one source line
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Line {
+    hits: usize,
+    /// rom address and `(taken, not taken)` of the conditional jumps on the line
+    branches: Vec<(usize, (usize, usize))>,
+}
+
+impl Line {
+    fn branches_covered(&self) -> usize {
+        self.branches
+            .iter()
+            .map(|(_, (taken, not_taken))| (*taken > 0) as usize + (*not_taken > 0) as usize)
+            .sum()
+    }
+}
+
+impl Coverage {
+    pub fn new(rom: &[CPUInstruction]) -> Self {
+        let branches = rom
+            .iter()
+            .map(|instruction| match instruction {
+                CPUInstruction::CInstruc(_, _, jump) if !matches!(jump, Jump::Null | Jump::JMP) => {
+                    Some((0, 0))
+                }
+                _ => None,
+            })
+            .collect();
+        Self {
+            hits: vec![0; rom.len()],
+            branches,
+        }
+    }
+
+    /// counts the instruction at `pc`, `next_pc` is where it went. a jump to the next instruction
+    /// counts as not taken
+    pub(crate) fn record(&mut self, pc: usize, next_pc: usize) {
+        self.hits[pc] += 1;
+        if let Some((taken, not_taken)) = &mut self.branches[pc] {
+            if next_pc == pc + 1 {
+                *not_taken += 1;
+            } else {
+                *taken += 1;
+            }
+        }
+    }
+
+    /// how often the instruction at `addr` was executed
+    pub fn hits(&self, addr: usize) -> usize {
+        self.hits.get(addr).copied().unwrap_or(0)
+    }
+
+    /// how often the conditional jump at `addr` was taken and not taken, `None` if there is none
+    pub fn branch(&self, addr: usize) -> Option<(usize, usize)> {
+        self.branches.get(addr).copied().flatten()
+    }
+
+    /// rom addresses that never ran
+    pub fn uncovered(&self) -> Vec<usize> {
+        (0..self.hits.len())
+            .filter(|&addr| self.hits[addr] == 0)
+            .collect()
+    }
+
+    /// conditional jumps that did not go both ways, with `(taken, not taken)`
+    pub fn partial_branches(&self) -> Vec<(usize, (usize, usize))> {
+        self.branches
+            .iter()
+            .enumerate()
+            .filter_map(|(addr, branch)| Some((addr, (*branch)?)))
+            .filter(|(_, (taken, not_taken))| *taken == 0 || *not_taken == 0)
+            .collect()
+    }
+
+    /// adds the counts of `other`, which has to be collected for the same rom
+    pub fn merge(&mut self, other: &Coverage) -> Result<(), String> {
+        if self.hits.len() != other.hits.len() {
+            return Err(format!(
+                "a coverage of {} instructions cannot be merged into one of {}",
+                other.hits.len(),
+                self.hits.len()
+            ));
+        }
+        for (hits, other) in self.hits.iter_mut().zip(&other.hits) {
+            *hits += other;
+        }
+        for (branch, other) in self.branches.iter_mut().zip(&other.branches) {
+            if let (Some((taken, not_taken)), Some((other_taken, other_not_taken))) =
+                (branch, other)
+            {
+                *taken += other_taken;
+                *not_taken += other_not_taken;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.hits.iter_mut().for_each(|hits| *hits = 0);
+        self.branches.iter_mut().flatten().for_each(|b| *b = (0, 0));
+    }
+
+    /// the coverage by line of `program`, lines without a source map are the rom address + 1,
+    /// which is the line in the .hack file
+    fn lines(&self, program: &AsmProgram) -> BTreeMap<usize, Line> {
+        let mut lines: BTreeMap<usize, Line> = BTreeMap::new();
+        for addr in 0..self.hits.len() {
+            let number = program.source_pos(addr).map_or(addr + 1, |pos| pos.line);
+            let line = lines.entry(number).or_default();
+            line.hits = line.hits.max(self.hits[addr]);
+            if let Some(branch) = self.branches[addr] {
+                line.branches.push((addr, branch));
+            }
+        }
+        lines
+    }
+
+    /// an lcov tracefile for the source `file` that `program` was assembled from. every conditional
+    /// jump is a block with branch 0 for taken and 1 for not taken
+    pub fn lcov(&self, program: &AsmProgram, file: &str) -> String {
+        let lines = self.lines(program);
+        let mut text = format!("TN:\nSF:{}\n", file);
+        for (number, line) in &lines {
+            for (addr, (taken, not_taken)) in &line.branches {
+                for (branch, count) in [taken, not_taken].into_iter().enumerate() {
+                    match line.hits {
+                        0 => writeln!(text, "BRDA:{},{},{},-", number, addr, branch),
+                        _ => writeln!(text, "BRDA:{},{},{},{}", number, addr, branch, count),
+                    }
+                    .unwrap();
+                }
+            }
+        }
+        let branches: usize = lines.values().map(|line| 2 * line.branches.len()).sum();
+        let covered: usize = lines.values().map(Line::branches_covered).sum();
+        write!(text, "BRF:{}\nBRH:{}\n", branches, covered).unwrap();
+        for (number, line) in &lines {
+            writeln!(text, "DA:{},{}", number, line.hits).unwrap();
+        }
+        let hit = lines.values().filter(|line| line.hits > 0).count();
+        write!(text, "LF:{}\nLH:{}\nend_of_record\n", lines.len(), hit).unwrap();
+        text
+    }
+
+    /// a cobertura xml report with one class for `file`
+    pub fn cobertura(&self, program: &AsmProgram, file: &str) -> String {
+        let lines = self.lines(program);
+        let hit = lines.values().filter(|line| line.hits > 0).count();
+        let branches: usize = lines.values().map(|line| 2 * line.branches.len()).sum();
+        let covered: usize = lines.values().map(Line::branches_covered).sum();
+        let line_rate = rate(hit, lines.len());
+        let branch_rate = rate(covered, branches);
+        let name = file
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or(file)
+            .trim_end_matches(".asm");
+
+        let mut text = String::from("<?xml version=\"1.0\" ?>\n");
+        writeln!(
+            text,
+            "<coverage line-rate=\"{}\" branch-rate=\"{}\" lines-covered=\"{}\" lines-valid=\"{}\" \
+             branches-covered=\"{}\" branches-valid=\"{}\" complexity=\"0\" version=\"0\" timestamp=\"0\">",
+            line_rate,
+            branch_rate,
+            hit,
+            lines.len(),
+            covered,
+            branches
+        )
+        .unwrap();
+        text.push_str("  <sources>\n    <source>.</source>\n  </sources>\n  <packages>\n");
+        writeln!(
+            text,
+            "    <package name=\".\" line-rate=\"{}\" branch-rate=\"{}\" complexity=\"0\">\n      <classes>",
+            line_rate, branch_rate
+        )
+        .unwrap();
+        writeln!(
+            text,
+            "        <class name=\"{}\" filename=\"{}\" line-rate=\"{}\" branch-rate=\"{}\" complexity=\"0\">",
+            escape(name),
+            escape(file),
+            line_rate,
+            branch_rate
+        )
+        .unwrap();
+        text.push_str("          <methods/>\n          <lines>\n");
+        for (number, line) in &lines {
+            write!(
+                text,
+                "            <line number=\"{}\" hits=\"{}\"",
+                number, line.hits
+            )
+            .unwrap();
+            if line.branches.is_empty() {
+                text.push_str(" branch=\"false\"/>\n");
+            } else {
+                let covered = line.branches_covered();
+                let total = 2 * line.branches.len();
+                writeln!(
+                    text,
+                    " branch=\"true\" condition-coverage=\"{}% ({}/{})\"/>",
+                    100 * covered / total,
+                    covered,
+                    total
+                )
+                .unwrap();
+            }
+        }
+        text.push_str(
+            "          </lines>\n        </class>\n      </classes>\n    </package>\n  </packages>\n</coverage>\n",
+        );
+        text
+    }
+}
+
+/// `covered / valid` with up to 4 decimals, 1 if there is nothing to cover
+fn rate(covered: usize, valid: usize) -> String {
+    if valid == 0 {
+        return String::from("1");
+    }
+    let rate = format!("{:.4}", covered as f64 / valid as f64);
+    rate.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,343 @@
+use super::parser::{get_comp, get_dest, get_jump, Token};
+use super::{CPUInstruction, Dest, Jump, SourcePos};
+use logos::Logos;
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    /// `(LOOP)`
+    Label,
+    /// `@21` or `@i`
+    AInstruction,
+    /// `dest=comp;jump`, its children are the parts and the whitespace between them
+    CInstruction,
+    Dest,
+    Equals,
+    Comp,
+    Semicolon,
+    Jump,
+    /// spaces and tabs
+    Whitespace,
+    Newline,
+    /// a `//` comment without the newline or a `/* */` comment
+    Comment,
+    /// input that is not hack assembly
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    /// byte range in the source
+    pub span: Range<usize>,
+    pub text: String,
+    /// only c-instructions have children, together they cover `text`
+    pub children: Vec<SyntaxNode>,
+}
+
+impl SyntaxNode {
+    fn new(kind: SyntaxKind, span: Range<usize>, code: &str) -> Self {
+        Self {
+            kind,
+            text: code[span.clone()].to_string(),
+            span,
+            children: Vec::new(),
+        }
+    }
+
+    /// true for whitespace, newlines and comments
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self.kind,
+            SyntaxKind::Whitespace | SyntaxKind::Newline | SyntaxKind::Comment
+        )
+    }
+}
+
+/// a lossless syntax tree of hack assembly, printing it gives back the source byte for byte
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxTree {
+    pub nodes: Vec<SyntaxNode>,
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.nodes
+            .iter()
+            .try_for_each(|node| write!(f, "{}", node.text))
+    }
+}
+
+impl SyntaxTree {
+    /// never fails, anything that is not hack assembly becomes a [`SyntaxKind::Error`] node.
+    /// unlike [`crate::cpu::parse`] a c-instruction has to be on one line
+    pub fn parse(code: &str) -> Self {
+        let tokens: Vec<(Token, Range<usize>)> = Token::lexer(code).spanned().collect();
+        let mut nodes = Vec::new();
+        let mut i = 0;
+        while let Some((token, span)) = tokens.get(i) {
+            i += 1;
+            let kind = match token {
+                Token::Ignore(_) => {
+                    push_trivia(&mut nodes, span.clone(), code);
+                    continue;
+                }
+                Token::Labal(_) => SyntaxKind::Label,
+                Token::Name(_) | Token::Number(_) => SyntaxKind::AInstruction,
+                Token::Unknown | Token::Eq | Token::Semic => SyntaxKind::Error,
+                _ => {
+                    let (node, next) = c_instruc(&tokens, i - 1, code);
+                    nodes.push(node);
+                    i = next;
+                    continue;
+                }
+            };
+            nodes.push(SyntaxNode::new(kind, span.clone(), code));
+        }
+        Self { nodes }
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.nodes
+            .iter()
+            .filter(|node| node.kind == SyntaxKind::Error)
+    }
+}
+
+/// splits the `Ignore` token of the lexer, line comments include their newline
+fn push_trivia(nodes: &mut Vec<SyntaxNode>, span: Range<usize>, code: &str) {
+    let text = &code[span.clone()];
+    let kind = match text {
+        " " | "\t" => SyntaxKind::Whitespace,
+        "\n" | "\r\n" => SyntaxKind::Newline,
+        _ => SyntaxKind::Comment,
+    };
+    let newline = if text.starts_with("//") {
+        text.len() - text.trim_end_matches(&['\r', '\n'][..]).len()
+    } else {
+        0
+    };
+    let end = span.end - newline;
+    nodes.push(SyntaxNode::new(kind, span.start..end, code));
+    if newline > 0 {
+        nodes.push(SyntaxNode::new(SyntaxKind::Newline, end..span.end, code));
+    }
+}
+
+/// the c-instruction starting at `tokens[start]` and the index of the token after it
+fn c_instruc(tokens: &[(Token, Range<usize>)], start: usize, code: &str) -> (SyntaxNode, usize) {
+    // token index of every part, the tokens between them are whitespace
+    let mut parts = Vec::new();
+    let mut comp = start;
+    if let Some(eq) = next_on_line(tokens, start + 1, code).filter(|&j| tokens[j].0 == Token::Eq) {
+        parts.push((SyntaxKind::Dest, start));
+        parts.push((SyntaxKind::Equals, eq));
+        comp = match next_on_line(tokens, eq + 1, code) {
+            Some(j) => j,
+            None => return (error(tokens, start, eq + 1, code), eq + 1),
+        };
+    }
+    parts.push((SyntaxKind::Comp, comp));
+    let mut end = comp + 1;
+    if let Some(semic) = next_on_line(tokens, end, code).filter(|&j| tokens[j].0 == Token::Semic) {
+        match next_on_line(tokens, semic + 1, code) {
+            Some(jump) => {
+                parts.push((SyntaxKind::Semicolon, semic));
+                parts.push((SyntaxKind::Jump, jump));
+                end = jump + 1;
+            }
+            None => return (error(tokens, start, semic + 1, code), semic + 1),
+        }
+    }
+
+    let valid = parts.iter().all(|&(kind, j)| match kind {
+        SyntaxKind::Dest => get_dest(&tokens[j].0).is_some(),
+        SyntaxKind::Comp => get_comp(&tokens[j].0).is_some(),
+        SyntaxKind::Jump => get_jump(&tokens[j].0).is_some(),
+        _ => true,
+    });
+    if !valid {
+        return (error(tokens, start, end, code), end);
+    }
+
+    let span = tokens[start].1.start..tokens[end - 1].1.end;
+    let mut node = SyntaxNode::new(SyntaxKind::CInstruction, span, code);
+    node.children = (start..end)
+        .map(|j| {
+            let kind = parts
+                .iter()
+                .find(|&&(_, part)| part == j)
+                .map_or(SyntaxKind::Whitespace, |&(kind, _)| kind);
+            SyntaxNode::new(kind, tokens[j].1.clone(), code)
+        })
+        .collect();
+    (node, end)
+}
+
+/// the index of the next token after spaces and tabs, `None` at the end of the line or file
+fn next_on_line(tokens: &[(Token, Range<usize>)], mut i: usize, code: &str) -> Option<usize> {
+    while let Some((token, span)) = tokens.get(i) {
+        match token {
+            Token::Ignore(_) if matches!(&code[span.clone()], " " | "\t") => i += 1,
+            Token::Ignore(_) => return None,
+            _ => return Some(i),
+        }
+    }
+    None
+}
+
+/// covers the tokens `start..end`
+fn error(tokens: &[(Token, Range<usize>)], start: usize, end: usize, code: &str) -> SyntaxNode {
+    let span = tokens[start].1.start..tokens[end - 1].1.end;
+    SyntaxNode::new(SyntaxKind::Error, span, code)
+}
+
+/// indentation of instructions, labels are not indented
+const INDENT: &str = "    ";
+
+/// one line of formatted output
+#[derive(Debug, Default)]
+struct OutLine {
+    label: bool,
+    code: Option<String>,
+    comment: Option<String>,
+}
+
+/// formats hack assembly: labels start at column 0, instructions are indented by four spaces,
+/// every label and instruction gets its own line, `dest` and `comp` use their canonical mnemonics,
+/// trailing comments of consecutive lines are aligned and runs of blank lines are collapsed.
+/// fails if the code contains anything that is not hack assembly
+pub fn format_asm(code: &str) -> Result<String, String> {
+    let tree = SyntaxTree::parse(code);
+    if let Some(error) = tree.errors().next() {
+        let pos = SourcePos::from_offsets(code, &[error.span.start])[0];
+        return Err(format!(
+            "{}:{}: cannot format `{}`",
+            pos.line, pos.column, error.text
+        ));
+    }
+
+    // `None` is a blank line
+    let mut lines: Vec<Option<OutLine>> = Vec::new();
+    let mut line_has_code = false;
+    let mut line_is_empty = true;
+    for node in &tree.nodes {
+        match node.kind {
+            SyntaxKind::Newline => {
+                if line_is_empty {
+                    lines.push(None);
+                }
+                line_has_code = false;
+                line_is_empty = true;
+            }
+            SyntaxKind::Whitespace => (),
+            SyntaxKind::Comment => {
+                let comment = node.text.trim_end().to_string();
+                match lines.last_mut() {
+                    Some(Some(line)) if line_has_code && line.comment.is_none() => {
+                        line.comment = Some(comment)
+                    }
+                    _ => lines.push(Some(OutLine {
+                        comment: Some(comment),
+                        ..OutLine::default()
+                    })),
+                }
+                line_is_empty = false;
+            }
+            kind => {
+                lines.push(Some(OutLine {
+                    label: kind == SyntaxKind::Label,
+                    code: Some(normalize(node)),
+                    comment: None,
+                }));
+                line_has_code = true;
+                line_is_empty = false;
+            }
+        }
+    }
+
+    // collapse blank lines and drop them at the start and end
+    let mut collapsed: Vec<Option<OutLine>> = Vec::new();
+    for line in lines {
+        if line.is_some() || matches!(collapsed.last(), Some(Some(_))) {
+            collapsed.push(line);
+        }
+    }
+    while let Some(None) = collapsed.last() {
+        collapsed.pop();
+    }
+
+    let indent = |line: &OutLine| if line.label { "" } else { INDENT };
+    let mut out = String::new();
+    let mut i = 0;
+    while i < collapsed.len() {
+        match &collapsed[i] {
+            None => {
+                out.push('\n');
+                i += 1;
+            }
+            Some(line) if line.code.is_none() => {
+                // a comment on its own line is indented like the code below it
+                let next_code = collapsed[i + 1..]
+                    .iter()
+                    .map_while(|line| line.as_ref())
+                    .find(|line| line.code.is_some());
+                let indent = next_code.map_or("", indent);
+                out.push_str(indent);
+                out.push_str(line.comment.as_deref().unwrap_or(""));
+                out.push('\n');
+                i += 1;
+            }
+            Some(_) => {
+                // consecutive lines of code, trailing comments are aligned within them
+                let run: Vec<&OutLine> = collapsed[i..]
+                    .iter()
+                    .map_while(|line| line.as_ref())
+                    .take_while(|line| line.code.is_some())
+                    .collect();
+                let width = |line: &OutLine| indent(line).len() + line.code.as_ref().unwrap().len();
+                let column = run
+                    .iter()
+                    .filter(|line| line.comment.is_some())
+                    .map(|line| width(line))
+                    .max()
+                    .unwrap_or(0)
+                    + 1;
+                for line in &run {
+                    out.push_str(indent(line));
+                    out.push_str(line.code.as_ref().unwrap());
+                    if let Some(comment) = &line.comment {
+                        out.push_str(&" ".repeat(column - width(line)));
+                        out.push_str(comment);
+                    }
+                    out.push('\n');
+                }
+                i += run.len();
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// the canonical text of a label or instruction
+fn normalize(node: &SyntaxNode) -> String {
+    if node.kind != SyntaxKind::CInstruction {
+        return node.text.clone();
+    }
+    let token = |kind| {
+        node.children
+            .iter()
+            .find(|child| child.kind == kind)
+            .and_then(|child| Token::lexer(&child.text).next())
+    };
+    let dest = token(SyntaxKind::Dest).map_or(Some(Dest::Null), |t| get_dest(&t));
+    let comp = token(SyntaxKind::Comp).and_then(|t| get_comp(&t));
+    let jump = token(SyntaxKind::Jump).map_or(Some(Jump::Null), |t| get_jump(&t));
+    match (comp, dest, jump) {
+        (Some(comp), Some(dest), Some(jump)) => {
+            CPUInstruction::CInstruc(comp, dest, jump).to_string()
+        }
+        _ => node.text.clone(),
+    }
+}
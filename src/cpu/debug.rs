@@ -0,0 +1,97 @@
+use super::HackCpu;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// a single access of an instruction to M, for reads `old` and `new` are the same
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub addr: usize,
+    pub kind: AccessKind,
+    pub old: i16,
+    pub new: i16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    D,
+    Pc,
+    Ram(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// `reg cmp value`, e.g. `D == 0`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub reg: Register,
+    pub cmp: Cmp,
+    pub value: i16,
+}
+
+impl Condition {
+    pub fn new(reg: Register, cmp: Cmp, value: i16) -> Self {
+        Self { reg, cmp, value }
+    }
+
+    pub fn holds(&self, cpu: &HackCpu) -> bool {
+        let val = match self.reg {
+            Register::A => cpu.a_reg(),
+            Register::D => cpu.d_reg(),
+            Register::Pc => cpu.pc() as i16,
+            Register::Ram(addr) => cpu.ram(addr),
+        };
+        match self.cmp {
+            Cmp::Eq => val == self.value,
+            Cmp::Ne => val != self.value,
+            Cmp::Lt => val < self.value,
+            Cmp::Le => val <= self.value,
+            Cmp::Gt => val > self.value,
+            Cmp::Ge => val >= self.value,
+        }
+    }
+}
+
+/// breakpoints are checked before an instruction executes
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    Address(usize),
+    /// a label of the program, see [`HackCpu::set_labels`]
+    Label(String),
+    /// stops whenever the condition holds
+    Condition(Condition),
+    /// stops at the address only if the condition holds
+    ConditionalAddress(usize, Condition),
+}
+
+/// watchpoints are checked after an instruction accessed M
+#[derive(Debug, Clone, PartialEq)]
+pub enum Watchpoint {
+    Read(Range<usize>),
+    Write(Range<usize>),
+    Access(Range<usize>),
+}
+
+impl Watchpoint {
+    pub fn matches(&self, access: &MemoryAccess) -> bool {
+        match (self, access.kind) {
+            (Watchpoint::Read(range), AccessKind::Read)
+            | (Watchpoint::Write(range), AccessKind::Write)
+            | (Watchpoint::Access(range), _) => range.contains(&access.addr),
+            _ => false,
+        }
+    }
+}
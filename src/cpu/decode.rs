@@ -0,0 +1,94 @@
+use super::{CPUInstruction, Comp};
+
+/// the control bits of a comp field turned into masks, so the alu needs no branches except for `f`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AluOp {
+    /// `y` is M instead of A
+    pub use_m: bool,
+    /// `x & zx` zeroes x when the zx bit is set
+    zx: i16,
+    /// `x ^ nx` negates x when the nx bit is set
+    nx: i16,
+    zy: i16,
+    ny: i16,
+    add: bool,
+    no: i16,
+}
+
+impl AluOp {
+    pub fn new(comp: Comp) -> Self {
+        let mask = |bit: bool| if bit { -1 } else { 0 };
+        Self {
+            use_m: comp.a(),
+            zx: !mask(comp.zx()),
+            nx: mask(comp.nx()),
+            zy: !mask(comp.zy()),
+            ny: mask(comp.ny()),
+            add: comp.f(),
+            no: mask(comp.no()),
+        }
+    }
+
+    #[inline(always)]
+    pub fn eval(self, x: i16, y: i16) -> i16 {
+        let x = (x & self.zx) ^ self.nx;
+        let y = (y & self.zy) ^ self.ny;
+        let out = if self.add { x.wrapping_add(y) } else { x & y };
+        out ^ self.no
+    }
+}
+
+pub(crate) const DEST_M: u8 = 0b001;
+pub(crate) const DEST_D: u8 = 0b010;
+pub(crate) const DEST_A: u8 = 0b100;
+
+pub(crate) const JUMP_GT: u8 = 0b001;
+pub(crate) const JUMP_EQ: u8 = 0b010;
+pub(crate) const JUMP_LT: u8 = 0b100;
+
+/// an instruction decoded once when the rom is loaded, cheap to copy and to execute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Op {
+    A(i16),
+    C {
+        alu: AluOp,
+        dest: u8,
+        jump: u8,
+        /// `0;JMP` without a destination, the only instruction [`super::HackCpu::is_halted`] has to look at
+        halt: bool,
+    },
+}
+
+impl Op {
+    pub fn decode(instruction: &CPUInstruction) -> Self {
+        match instruction {
+            CPUInstruction::AInstruc(val) => Op::A(*val & 0x7fff),
+            CPUInstruction::CInstruc(comp, dest, jump) => {
+                let dest = dest.clone() as u8;
+                let jump = jump.clone() as u8;
+                Op::C {
+                    alu: AluOp::new(*comp),
+                    dest,
+                    jump,
+                    halt: dest == 0 && jump == 0b111,
+                }
+            }
+        }
+    }
+
+    pub fn decode_all(program: &[CPUInstruction]) -> Vec<Self> {
+        program.iter().map(Op::decode).collect()
+    }
+}
+
+/// the jump bit that has to be set for the cpu to jump on `val`
+#[inline(always)]
+pub(crate) fn jump_cond(val: i16) -> u8 {
+    if val < 0 {
+        JUMP_LT
+    } else if val == 0 {
+        JUMP_EQ
+    } else {
+        JUMP_GT
+    }
+}
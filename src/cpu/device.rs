@@ -0,0 +1,297 @@
+use std::any::Any;
+use std::fmt;
+use std::io::Write;
+use std::ops::Range;
+
+/// something mapped into the address space of a [`crate::cpu::HackCpu`] with
+/// [`crate::cpu::HackCpu::map_device`]. offsets are relative to the address it is mapped at
+pub trait Device: DeviceClone + fmt::Debug {
+    /// number of consecutive addresses the device occupies
+    fn size(&self) -> usize {
+        1
+    }
+
+    /// the value at `offset` without side effects, this is what [`crate::cpu::HackCpu::ram`] shows
+    fn peek(&self, offset: usize, cycle: usize) -> i16;
+
+    /// the value at `offset` as seen by an instruction reading M
+    fn read(&mut self, offset: usize, cycle: usize) -> i16 {
+        self.peek(offset, cycle)
+    }
+
+    fn write(&mut self, offset: usize, val: i16, cycle: usize);
+}
+
+/// lets a cpu with devices be cloned and its devices be downcast, implemented for every `Clone` device
+pub trait DeviceClone {
+    fn clone_box(&self) -> Box<dyn Device>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Device + Clone + 'static> DeviceClone for T {
+    fn clone_box(&self) -> Box<dyn Device> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Clone for Box<dyn Device> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// collects the characters written to it, reads give 0. the codes are the ones of the hack
+/// character set, so 128 is a newline
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConsoleOut {
+    output: String,
+    echo: bool,
+}
+
+impl ConsoleOut {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// a console that also prints every character to stdout
+    pub fn stdout() -> Self {
+        Self {
+            output: String::new(),
+            echo: true,
+        }
+    }
+
+    /// everything written so far
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn clear(&mut self) {
+        self.output.clear();
+    }
+}
+
+impl Device for ConsoleOut {
+    fn peek(&self, _offset: usize, _cycle: usize) -> i16 {
+        0
+    }
+
+    fn write(&mut self, _offset: usize, val: i16, _cycle: usize) {
+        let c = match val {
+            128 => '\n',
+            _ => char::from_u32(val as u16 as u32).unwrap_or(char::REPLACEMENT_CHARACTER),
+        };
+        self.output.push(c);
+        if self.echo {
+            let mut stdout = std::io::stdout();
+            let _ = write!(stdout, "{}", c);
+            let _ = stdout.flush();
+        }
+    }
+}
+
+/// two words with the number of cycles since the cpu was created or the counter was last written,
+/// the low 16 bits at offset 0 and the next 16 at offset 1. writing any value resets it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CycleCounter {
+    start: usize,
+}
+
+impl CycleCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Device for CycleCounter {
+    fn size(&self) -> usize {
+        2
+    }
+
+    fn peek(&self, offset: usize, cycle: usize) -> i16 {
+        let count = cycle.wrapping_sub(self.start);
+        (count >> (16 * offset)) as u16 as i16
+    }
+
+    fn write(&mut self, _offset: usize, _val: i16, cycle: usize) {
+        self.start = cycle;
+    }
+}
+
+/// every read gives the next number of a xorshift generator, writing a value reseeds it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Random {
+    state: u16,
+}
+
+impl Random {
+    pub fn new(seed: u16) -> Self {
+        // xorshift never leaves 0
+        Self { state: seed.max(1) }
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new(0xace1)
+    }
+}
+
+impl Device for Random {
+    /// the number the last read returned
+    fn peek(&self, _offset: usize, _cycle: usize) -> i16 {
+        self.state as i16
+    }
+
+    fn read(&mut self, _offset: usize, _cycle: usize) -> i16 {
+        self.state ^= self.state << 7;
+        self.state ^= self.state >> 9;
+        self.state ^= self.state << 8;
+        self.state as i16
+    }
+
+    fn write(&mut self, _offset: usize, val: i16, _cycle: usize) {
+        *self = Self::new(val as u16);
+    }
+}
+
+/// the 15 bit address space of the cpu: plain ram with devices mapped over parts of it
+#[derive(Debug, Clone)]
+pub(crate) struct Bus {
+    ram: Vec<i16>,
+    devices: Vec<(usize, Range<usize>, Box<dyn Device>)>,
+}
+
+impl Bus {
+    pub(crate) fn new(size: usize) -> Self {
+        Self {
+            ram: vec![0; size],
+            devices: Vec::new(),
+        }
+    }
+
+    pub(crate) fn map(
+        &mut self,
+        id: usize,
+        start: usize,
+        device: Box<dyn Device>,
+    ) -> Result<(), String> {
+        let range = start..start + device.size();
+        if range.is_empty() || range.end > self.ram.len() {
+            return Err(format!(
+                "a device at {}..{} does not fit into the {} addresses",
+                range.start,
+                range.end,
+                self.ram.len()
+            ));
+        }
+        if let Some((_, other, _)) = self
+            .devices
+            .iter()
+            .find(|(_, other, _)| other.start < range.end && range.start < other.end)
+        {
+            return Err(format!(
+                "a device at {}..{} overlaps the one at {}..{}",
+                range.start, range.end, other.start, other.end
+            ));
+        }
+        self.devices.push((id, range, device));
+        Ok(())
+    }
+
+    pub(crate) fn unmap(&mut self, id: usize) -> Option<Box<dyn Device>> {
+        let i = self.devices.iter().position(|(i, _, _)| *i == id)?;
+        Some(self.devices.remove(i).2)
+    }
+
+    pub(crate) fn device(&self, id: usize) -> Option<&dyn Device> {
+        self.devices
+            .iter()
+            .find(|(i, _, _)| *i == id)
+            .map(|(_, _, device)| device.as_ref())
+    }
+
+    pub(crate) fn device_mut(&mut self, id: usize) -> Option<&mut dyn Device> {
+        self.devices
+            .iter_mut()
+            .find(|(i, _, _)| *i == id)
+            .map(|(_, _, device)| device.as_mut() as &mut dyn Device)
+    }
+
+    pub(crate) fn is_mapped(&self, addr: usize) -> bool {
+        self.find(addr).is_some()
+    }
+
+    fn find(&self, addr: usize) -> Option<usize> {
+        self.devices
+            .iter()
+            .position(|(_, range, _)| range.contains(&addr))
+    }
+
+    #[inline(always)]
+    pub(crate) fn peek(&self, addr: usize, cycle: usize) -> i16 {
+        if self.devices.is_empty() {
+            return self.ram[addr];
+        }
+        match self.find(addr) {
+            Some(i) => {
+                let (_, range, device) = &self.devices[i];
+                device.peek(addr - range.start, cycle)
+            }
+            None => self.ram[addr],
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn read(&mut self, addr: usize, cycle: usize) -> i16 {
+        if self.devices.is_empty() {
+            return self.ram[addr];
+        }
+        match self.find(addr) {
+            Some(i) => {
+                let (_, range, device) = &mut self.devices[i];
+                device.read(addr - range.start, cycle)
+            }
+            None => self.ram[addr],
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn write(&mut self, addr: usize, val: i16, cycle: usize) {
+        if self.devices.is_empty() {
+            self.ram[addr] = val;
+            return;
+        }
+        match self.find(addr) {
+            Some(i) => {
+                let (_, range, device) = &mut self.devices[i];
+                device.write(addr - range.start, val, cycle);
+            }
+            None => self.ram[addr] = val,
+        }
+    }
+
+    /// writes `val` to ram without going through a device, used to undo instructions. what devices
+    /// did cannot be undone, so addresses with a device are left alone
+    pub(crate) fn restore(&mut self, addr: usize, val: i16) {
+        if !self.is_mapped(addr) {
+            self.ram[addr] = val;
+        }
+    }
+
+    pub(crate) fn peek_range(&self, range: Range<usize>, cycle: usize) -> Vec<i16> {
+        if self.devices.is_empty() {
+            return self.ram[range].to_vec();
+        }
+        range.map(|addr| self.peek(addr, cycle)).collect()
+    }
+}
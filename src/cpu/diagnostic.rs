@@ -0,0 +1,62 @@
+use super::{AsmProgram, SourcePos};
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    /// legal code that is almost always a bug
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// byte range in the source
+    pub span: Range<usize>,
+    /// where `span` starts
+    pub pos: SourcePos,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.pos.line, self.pos.column, severity, self.message
+        )
+    }
+}
+
+/// result of [`crate::cpu::assemble`], diagnostics are sorted by their position in the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembly {
+    /// `None` if there is at least one error
+    pub program: Option<AsmProgram>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Assembly {
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.is_error())
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| !d.is_error())
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+}
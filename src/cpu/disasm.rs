@@ -0,0 +1,80 @@
+use super::{ml2asm, CPUInstruction, Dest, Jump};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// turns machine code into hack assembly that assembles to the same words.
+/// an a-instruction followed by a jump gets a label `L<addr>` for its target, one followed by an access to M
+/// gets the name of a predefined symbol if there is one. words without an assembly form are an error
+pub fn disassemble(ml: &[u16]) -> Result<String, String> {
+    for (addr, &word) in ml.iter().enumerate() {
+        if word & 0x8000 != 0 && word & 0x6000 != 0x6000 {
+            return Err(format!(
+                "the word {:016b} at {} is a c-instruction without bits 13 and 14 set",
+                word, addr
+            ));
+        }
+    }
+    let asm = ml2asm(ml.to_vec())?;
+    for (addr, instruction) in asm.iter().enumerate() {
+        if let CPUInstruction::CInstruc(comp, _, _) = instruction {
+            if comp.mnemonic().is_none() {
+                return Err(format!(
+                    "the instruction at {} computes the undocumented {}, which has no mnemonic",
+                    addr, comp
+                ));
+            }
+        }
+    }
+
+    let mut targets = BTreeSet::new();
+    for pair in asm.windows(2) {
+        if let [CPUInstruction::AInstruc(val), CPUInstruction::CInstruc(_, _, jump)] = pair {
+            if *jump != Jump::Null && *val as usize <= asm.len() {
+                targets.insert(*val as usize);
+            }
+        }
+    }
+
+    let mut text = String::new();
+    for (addr, instruction) in asm.iter().enumerate() {
+        if targets.contains(&addr) {
+            writeln!(text, "({})", label(addr)).unwrap();
+        }
+        match symbol(&asm, addr, &targets) {
+            Some(symbol) => writeln!(text, "    @{}", symbol).unwrap(),
+            None => writeln!(text, "    {}", instruction).unwrap(),
+        }
+    }
+    if targets.contains(&asm.len()) {
+        writeln!(text, "({})", label(asm.len())).unwrap();
+    }
+    Ok(text)
+}
+
+fn label(addr: usize) -> String {
+    format!("L{}", addr)
+}
+
+/// the name for the a-instruction at `addr` depending on how the next instruction uses A
+fn symbol(asm: &[CPUInstruction], addr: usize, targets: &BTreeSet<usize>) -> Option<String> {
+    let val = match asm[addr] {
+        CPUInstruction::AInstruc(val) => val as usize,
+        CPUInstruction::CInstruc(..) => return None,
+    };
+    match asm.get(addr + 1) {
+        Some(CPUInstruction::CInstruc(_, _, jump)) if *jump != Jump::Null => {
+            targets.contains(&val).then(|| label(val))
+        }
+        Some(CPUInstruction::CInstruc(comp, dest, _))
+            if comp.a() || matches!(dest, Dest::M | Dest::MD | Dest::AM | Dest::AMD) =>
+        {
+            match val {
+                0..=15 => Some(format!("R{}", val)),
+                _ if val == crate::SCREEN => Some(String::from("SCREEN")),
+                _ if val == crate::KBD => Some(String::from("KBD")),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
@@ -1,111 +1,714 @@
-use crate::cpu::{Comp, Dest, CPUInstruction, Jump};
+use crate::cpu::decode::{jump_cond, Op, DEST_A, DEST_D, DEST_M};
+use crate::cpu::device::Bus;
+use crate::cpu::history::{History, JournalEntry};
+use crate::cpu::{
+    ml2asm, AccessKind, AsmProgram, Breakpoint, CPUInstruction, Coverage, Device, Keyboard,
+    MachineProfile, MemCheck, MemoryAccess, Profile, Screen, Snapshot, Trace, TraceEntry,
+    Watchpoint, SCREEN_WORDS,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
 
+/// why a call to [`HackCpu::run`] or [`HackCpu::run_until`] returned
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunOutcome {
+    /// the program reached the canonical `(END) @END 0;JMP` self loop
+    Halted { cycles: usize },
+    /// `max_cycles` instructions were executed without halting
+    BudgetExhausted { cycles: usize },
+    /// the program counter points past the last instruction in rom
+    OutOfRom { pc: usize, cycles: usize },
+    /// the predicate passed to [`HackCpu::run_until`] returned true
+    Stopped { cycles: usize },
+    /// the breakpoint with this id is hit, the instruction at pc has not been executed yet
+    Breakpoint { id: usize, cycles: usize },
+    /// the last instruction made an access that matches the watchpoint with this id
+    Watchpoint {
+        id: usize,
+        access: MemoryAccess,
+        cycles: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CpuError {
+    /// the program counter points past the last instruction in rom
+    PcOutOfRom { pc: usize },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::PcOutOfRom { pc } => write!(f, "pc {} is outside of rom", pc),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct HackCpu {
     d_reg: i16,
     a_reg: i16,
     pc: usize,
+    cycles: usize,
 
-    ram: Vec<i16>,
+    machine: MachineProfile,
+    /// `ram_size - 1` and `rom_size - 1` of the machine, the bits of A that select an address
+    ram_mask: usize,
+    rom_mask: usize,
+    /// ram with the devices mapped over it
+    bus: Bus,
     rom: Vec<CPUInstruction>,
+    /// `rom` decoded once, this is what actually gets executed
+    ops: Vec<Op>,
+
+    keyboard: Option<Keyboard>,
+    trace: Option<Trace>,
+    history: Option<History>,
+    profile: Option<Profile>,
+    coverage: Option<Coverage>,
+    memcheck: Option<MemCheck>,
+
+    last_read: Option<MemoryAccess>,
+    last_write: Option<MemoryAccess>,
+
+    labels: HashMap<String, usize>,
+    breakpoints: Vec<(usize, Breakpoint)>,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_id: usize,
+    /// a breakpoint fired here, so the next run steps over it
+    resume_pc: Option<usize>,
 }
 
 impl HackCpu {
     pub fn new(program: Vec<CPUInstruction>) -> Self {
+        Self::build(program, MachineProfile::default())
+    }
+
+    /// a cpu with the memory map and rom size of `machine` instead of the official ones
+    pub fn with_machine(
+        program: Vec<CPUInstruction>,
+        machine: MachineProfile,
+    ) -> Result<Self, String> {
+        machine.validate()?;
+        Ok(Self::build(program, machine))
+    }
+
+    fn build(program: Vec<CPUInstruction>, machine: MachineProfile) -> Self {
         Self {
             d_reg: 0,
             a_reg: 0,
             pc: 0,
+            cycles: 0,
 
-            ram: vec![0; 0xffff],
+            bus: Bus::new(machine.ram_size),
+            ram_mask: machine.ram_size - 1,
+            rom_mask: machine.rom_size - 1,
+            machine,
+            ops: Op::decode_all(&program),
             rom: program,
+
+            keyboard: None,
+            trace: None,
+            history: None,
+            profile: None,
+            coverage: None,
+            memcheck: None,
+
+            last_read: None,
+            last_write: None,
+
+            labels: HashMap::new(),
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_id: 0,
+            resume_pc: None,
         }
     }
 
-    pub fn step(&mut self) {
-        match self.rom[self.pc].clone() {
-            CPUInstruction::AInstruc(val) => self.a_reg = val,
-            CPUInstruction::CInstruc(comp, dest, jump) => {
-                let val = self.compute(comp);
-                self.store_dest(dest, val);
-                self.jump(jump, val);
-            }
+    /// loads the instructions of `program` and its labels for [`Breakpoint::Label`]
+    pub fn from_program(program: &AsmProgram) -> Self {
+        let mut cpu = Self::new(program.instructions.clone());
+        cpu.set_labels(program.labels.clone());
+        cpu
+    }
+
+    /// creates a cpu from machine code as returned by [`crate::cpu::str2ml`]
+    pub fn from_ml(ml: Vec<u16>) -> Result<Self, String> {
+        Ok(Self::new(ml2asm(ml)?))
+    }
+
+    /// replaces the rom and sets the program counter to 0, registers and ram are kept
+    pub fn load_rom(&mut self, program: Vec<CPUInstruction>) {
+        self.ops = Op::decode_all(&program);
+        if self.profile.is_some() {
+            self.profile = Some(Profile::new(program.len()));
         }
+        if self.coverage.is_some() {
+            self.coverage = Some(Coverage::new(&program));
+        }
+        self.rom = program;
+        self.pc = 0;
+    }
+
+    /// like [`HackCpu::load_rom`] but takes machine code
+    pub fn load_ml(&mut self, ml: Vec<u16>) -> Result<(), String> {
+        self.load_rom(ml2asm(ml)?);
+        Ok(())
+    }
+
+    pub fn rom(&self) -> &[CPUInstruction] {
+        &self.rom
+    }
+
+    pub fn machine(&self) -> &MachineProfile {
+        &self.machine
+    }
+
+    /// mirrors the reset pin of the hack computer, only the program counter is set to 0
+    pub fn reset(&mut self) {
+        self.pc = 0;
+    }
+
+    pub fn a_reg(&self) -> i16 {
+        self.a_reg
     }
 
-    fn jump(&mut self, jump: Jump, val: i16) {
-        let should_jmp = match jump {
-            Jump::Null => false,
-            Jump::JGT => val > 0,
-            Jump::JEQ => val == 0,
-            Jump::JGE => val >= 0,
-            Jump::JLT => val < 0,
-            Jump::JNE => val != 0,
-            Jump::JLE => val <= 0,
-            Jump::JMP => true,
+    pub fn set_a_reg(&mut self, val: i16) {
+        self.a_reg = val;
+    }
+
+    pub fn d_reg(&self) -> i16 {
+        self.d_reg
+    }
+
+    pub fn set_d_reg(&mut self, val: i16) {
+        self.d_reg = val;
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    /// the word at `addr`, mapped devices are peeked so reading has no side effects
+    pub fn ram(&self, addr: usize) -> i16 {
+        self.bus.peek(addr, self.cycles)
+    }
+
+    /// writes go to the device mapped at `addr` if there is one
+    pub fn set_ram(&mut self, addr: usize, val: i16) {
+        self.bus.write(addr, val, self.cycles);
+        if let Some(memcheck) = &mut self.memcheck {
+            memcheck.mark_written(addr);
+        }
+    }
+
+    pub fn ram_range(&self, range: Range<usize>) -> Vec<i16> {
+        self.bus.peek_range(range, self.cycles)
+    }
+
+    /// writes `values` to consecutive addresses starting at `start`
+    pub fn set_ram_range(&mut self, start: usize, values: &[i16]) {
+        for (i, &val) in values.iter().enumerate() {
+            self.set_ram(start + i, val);
+        }
+    }
+
+    /// maps `device` at `addr` and returns its id. reads and writes of M in its range go to the
+    /// device instead of ram, it may overlay the screen or the keyboard but not another device.
+    /// the screen and the keyboard themselves are plain ram at the addresses of the
+    /// [`MachineProfile`], not devices. a device over them is what [`HackCpu::screen`] shows and
+    /// what the [`Keyboard`] writes to
+    pub fn map_device(
+        &mut self,
+        addr: usize,
+        device: impl Device + 'static,
+    ) -> Result<usize, String> {
+        self.bus.map(self.next_id + 1, addr, Box::new(device))?;
+        self.next_id += 1;
+        Ok(self.next_id)
+    }
+
+    /// removes a device, the ram below it shows again
+    pub fn unmap_device(&mut self, id: usize) -> Option<Box<dyn Device>> {
+        self.bus.unmap(id)
+    }
+
+    /// the device with this id if it is a `T`
+    pub fn device<T: Device + 'static>(&self, id: usize) -> Option<&T> {
+        self.bus.device(id)?.as_any().downcast_ref()
+    }
+
+    pub fn device_mut<T: Device + 'static>(&mut self, id: usize) -> Option<&mut T> {
+        self.bus.device_mut(id)?.as_any_mut().downcast_mut()
+    }
+
+    /// number of instructions executed since the cpu was created
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /// lets `keyboard` drive the `KBD` register, its events are relative to [`HackCpu::cycles`]
+    pub fn set_keyboard(&mut self, keyboard: Keyboard) {
+        self.keyboard = Some(keyboard);
+    }
+
+    pub fn keyboard(&self) -> Option<&Keyboard> {
+        self.keyboard.as_ref()
+    }
+
+    /// records the last `capacity` executed instructions, an existing trace is discarded
+    pub fn enable_trace(&mut self, capacity: usize) {
+        self.trace = Some(Trace::new(capacity));
+    }
+
+    pub fn disable_trace(&mut self) -> Option<Trace> {
+        self.trace.take()
+    }
+
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    /// journals the last `capacity` instructions so they can be undone with [`HackCpu::step_back`]
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(History::new(capacity));
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// number of instructions that can be undone
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, |history| history.len())
+    }
+
+    /// undoes the last instruction, returns false if the journal is empty. writes to devices are not undone
+    pub fn step_back(&mut self) -> bool {
+        let entry = match self.history.as_mut().and_then(|history| history.pop()) {
+            Some(entry) => entry,
+            None => return false,
         };
 
-        if should_jmp {
-            self.pc = self.a_reg as usize;
-        } else {
-            self.pc += 1;
+        self.pc = entry.pc;
+        self.a_reg = entry.a_reg;
+        self.d_reg = entry.d_reg;
+        if let Some((addr, old)) = entry.write {
+            self.bus.restore(addr, old);
         }
+        if let Some((old, position)) = entry.keyboard {
+            self.bus.restore(self.machine.keyboard, old);
+            if let Some(keyboard) = &mut self.keyboard {
+                keyboard.set_position(position);
+            }
+        }
+        self.cycles -= 1;
+        self.last_read = None;
+        self.last_write = None;
+        self.resume_pc = None;
+        true
+    }
+
+    /// counts how often every instruction is executed, an existing profile is discarded
+    pub fn enable_profile(&mut self) {
+        self.profile = Some(Profile::new(self.rom.len()));
+    }
+
+    pub fn disable_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
     }
 
-    fn store_dest(&mut self, dest: Dest, val: i16) {
-        match dest {
-            Dest::Null => (),
-            Dest::M => self.ram[self.a_reg as usize] = val,
-            Dest::D => self.d_reg = val,
-            Dest::MD => {
-                self.ram[self.a_reg as usize] = val;
-                self.d_reg = val;
+    /// records which instructions run and which way conditional jumps go, an existing coverage is
+    /// discarded
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new(&self.rom));
+    }
+
+    pub fn disable_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// reports reads of ram words that were never written, writes to the keyboard and accesses above
+    /// it, an existing check is discarded. words set with [`HackCpu::set_ram`] count as written
+    pub fn enable_memcheck(&mut self) {
+        self.memcheck = Some(MemCheck::new(self.machine.ram_size));
+    }
+
+    pub fn disable_memcheck(&mut self) -> Option<MemCheck> {
+        self.memcheck.take()
+    }
+
+    pub fn memcheck(&self) -> Option<&MemCheck> {
+        self.memcheck.as_ref()
+    }
+
+    /// remembers the current state, restoring it needs the history to reach back far enough
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cycles: self.cycles,
+            pc: self.pc,
+            a_reg: self.a_reg,
+            d_reg: self.d_reg,
+        }
+    }
+
+    /// steps back until the cycle of `snapshot` and restores its registers. ram written by instructions
+    /// is undone, edits with [`HackCpu::set_ram`] and [`HackCpu::set_ram_range`] are not
+    pub fn rewind(&mut self, snapshot: &Snapshot) -> Result<(), String> {
+        if snapshot.cycles > self.cycles {
+            return Err(format!(
+                "snapshot at cycle {} is in the future, the cpu is at cycle {}",
+                snapshot.cycles, self.cycles
+            ));
+        }
+        if self.cycles - snapshot.cycles > self.history_len() {
+            return Err(format!(
+                "the history only reaches back to cycle {} but the snapshot is at cycle {}",
+                self.cycles - self.history_len(),
+                snapshot.cycles
+            ));
+        }
+
+        while self.cycles > snapshot.cycles {
+            self.step_back();
+        }
+        // the registers may have been changed with the setters in between
+        self.pc = snapshot.pc;
+        self.a_reg = snapshot.a_reg;
+        self.d_reg = snapshot.d_reg;
+        Ok(())
+    }
+
+    /// the value of M read by the last instruction
+    pub fn last_read(&self) -> Option<MemoryAccess> {
+        self.last_read
+    }
+
+    /// the value of M written by the last instruction
+    pub fn last_write(&self) -> Option<MemoryAccess> {
+        self.last_write
+    }
+
+    /// labels used to resolve [`Breakpoint::Label`], see [`AsmProgram::labels`]
+    pub fn set_labels(&mut self, labels: HashMap<String, usize>) {
+        self.labels = labels;
+    }
+
+    /// returns the id of the breakpoint or an error if it refers to an unknown label
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> Result<usize, String> {
+        if let Breakpoint::Label(label) = &breakpoint {
+            if !self.labels.contains_key(label) {
+                return Err(format!("unknown label {}", label));
             }
-            Dest::A => self.a_reg = val,
-            Dest::AM => {
-                self.a_reg = val;
-                self.ram[self.a_reg as usize] = val;
+        }
+        self.next_id += 1;
+        self.breakpoints.push((self.next_id, breakpoint));
+        Ok(self.next_id)
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.next_id += 1;
+        self.watchpoints.push((self.next_id, watchpoint));
+        self.next_id
+    }
+
+    /// removes a breakpoint or watchpoint, returns false if there is none with this id
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        let len = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|(i, _)| *i != id);
+        self.watchpoints.retain(|(i, _)| *i != id);
+        len != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    fn hit_breakpoint(&self) -> Option<usize> {
+        self.breakpoints.iter().find_map(|(id, breakpoint)| {
+            let hit = match breakpoint {
+                Breakpoint::Address(addr) => *addr == self.pc,
+                Breakpoint::Label(label) => self.labels.get(label) == Some(&self.pc),
+                Breakpoint::Condition(condition) => condition.holds(self),
+                Breakpoint::ConditionalAddress(addr, condition) => {
+                    *addr == self.pc && condition.holds(self)
+                }
+            };
+            hit.then_some(*id)
+        })
+    }
+
+    fn hit_watchpoint(&self) -> Option<(usize, MemoryAccess)> {
+        self.watchpoints.iter().find_map(|(id, watchpoint)| {
+            [self.last_read, self.last_write]
+                .into_iter()
+                .flatten()
+                .find(|access| watchpoint.matches(access))
+                .map(|access| (*id, access))
+        })
+    }
+
+    /// snapshot of the screen memory map starting at `SCREEN`
+    pub fn screen(&self) -> Screen {
+        let screen = self.machine.screen;
+        Screen::from_ram(&self.ram_range(screen..screen + SCREEN_WORDS))
+    }
+
+    /// executes at most `max_cycles` instructions
+    pub fn run(&mut self, max_cycles: usize) -> RunOutcome {
+        if !self.is_instrumented() {
+            return self.run_fast(max_cycles);
+        }
+
+        let mut cycles = 0;
+        let outcome = self.run_until(|_| {
+            cycles += 1;
+            cycles > max_cycles
+        });
+
+        match outcome {
+            RunOutcome::Stopped { cycles } => RunOutcome::BudgetExhausted { cycles },
+            outcome => outcome,
+        }
+    }
+
+    /// true if anything has to look at the cpu between two instructions
+    fn is_instrumented(&self) -> bool {
+        self.trace.is_some()
+            || self.history.is_some()
+            || self.profile.is_some()
+            || self.coverage.is_some()
+            || self.memcheck.is_some()
+            || !self.breakpoints.is_empty()
+            || !self.watchpoints.is_empty()
+    }
+
+    /// [`HackCpu::run`] without tracing, history, profiling, coverage, memory checks and breakpoints.
+    /// the keyboard is handled by running in chunks up to its next event and letting
+    /// [`HackCpu::step`] poll it
+    fn run_fast(&mut self, max_cycles: usize) -> RunOutcome {
+        self.resume_pc = None;
+        let start = self.cycles;
+        let end = start.saturating_add(max_cycles);
+        loop {
+            let until = match self.keyboard.as_ref().and_then(|k| k.next_cycle()) {
+                Some(cycle) => cycle.clamp(self.cycles, end),
+                None => end,
+            };
+            while self.cycles < until {
+                let op = match self.ops.get(self.pc) {
+                    Some(&op) => op,
+                    None => {
+                        return RunOutcome::OutOfRom {
+                            pc: self.pc,
+                            cycles: self.cycles - start,
+                        }
+                    }
+                };
+                if let Op::C { halt: true, .. } = op {
+                    if self.is_halted() {
+                        return RunOutcome::Halted {
+                            cycles: self.cycles - start,
+                        };
+                    }
+                }
+                self.exec(op);
+                self.cycles += 1;
             }
-            Dest::AD => {
-                self.a_reg = val;
-                self.d_reg = val;
+
+            if self.is_halted() {
+                return RunOutcome::Halted {
+                    cycles: self.cycles - start,
+                };
             }
-            Dest::AMD => {
-                self.a_reg = val;
-                self.ram[self.a_reg as usize] = val;
-                self.d_reg = val;
+            if self.cycles >= end {
+                return RunOutcome::BudgetExhausted {
+                    cycles: self.cycles - start,
+                };
+            }
+            // a key event is due
+            if let Err(CpuError::PcOutOfRom { pc }) = self.step() {
+                return RunOutcome::OutOfRom {
+                    pc,
+                    cycles: self.cycles - start,
+                };
+            }
+        }
+    }
+
+    /// executes instructions until `predicate` returns true, the program halts or leaves the rom
+    /// or a breakpoint or watchpoint is hit. the predicate is checked before every instruction
+    pub fn run_until<F>(&mut self, mut predicate: F) -> RunOutcome
+    where
+        F: FnMut(&HackCpu) -> bool,
+    {
+        let mut cycles = 0;
+        let resume_pc = self.resume_pc.take();
+        loop {
+            let resuming = cycles == 0 && resume_pc == Some(self.pc);
+            if !resuming && !self.breakpoints.is_empty() {
+                if let Some(id) = self.hit_breakpoint() {
+                    self.resume_pc = Some(self.pc);
+                    return RunOutcome::Breakpoint { id, cycles };
+                }
+            }
+            if self.is_halted() {
+                return RunOutcome::Halted { cycles };
+            }
+            if predicate(self) {
+                return RunOutcome::Stopped { cycles };
+            }
+
+            match self.step() {
+                Ok(()) => cycles += 1,
+                Err(CpuError::PcOutOfRom { pc }) => return RunOutcome::OutOfRom { pc, cycles },
+            }
+
+            if !self.watchpoints.is_empty() {
+                if let Some((id, access)) = self.hit_watchpoint() {
+                    return RunOutcome::Watchpoint { id, access, cycles };
+                }
+            }
+        }
+    }
+
+    /// true if the next instruction is an unconditional jump to itself or to the `@X` directly above it,
+    /// which is how hack programs signal that they are done
+    pub fn is_halted(&self) -> bool {
+        if let Some(Op::C { halt: true, .. }) = self.ops.get(self.pc) {
+            let target = self.a_reg as u16 as usize & self.rom_mask;
+            target == self.pc
+                || (self.pc.checked_sub(1) == Some(target) && self.ops[target] == Op::A(self.a_reg))
+        } else {
+            false
+        }
+    }
+
+    pub fn step(&mut self) -> Result<(), CpuError> {
+        let op = match self.ops.get(self.pc) {
+            Some(&op) => op,
+            None => return Err(CpuError::PcOutOfRom { pc: self.pc }),
+        };
+
+        let mut keyboard_undo = None;
+        if let Some(keyboard) = &mut self.keyboard {
+            let position = keyboard.position();
+            if let Some(code) = keyboard.poll(self.cycles) {
+                keyboard_undo = Some((self.ram(self.machine.keyboard), position));
+                self.bus.write(self.machine.keyboard, code, self.cycles);
             }
         }
+        let undo = self
+            .history
+            .as_ref()
+            .map(|_| (self.pc, self.a_reg, self.d_reg));
+
+        let pc = self.pc;
+        self.exec(op);
+        if let Some(profile) = &mut self.profile {
+            profile.record(pc, self.pc);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(pc, self.pc);
+        }
+        if let Some(memcheck) = &mut self.memcheck {
+            let accesses = [self.last_read, self.last_write];
+            memcheck.record(pc, self.cycles, accesses, &self.machine, &self.bus);
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.push(TraceEntry {
+                cycle: self.cycles,
+                pc,
+                instruction: self.rom[pc].clone(),
+                a_reg: self.a_reg,
+                d_reg: self.d_reg,
+                write: self.last_write,
+            });
+        }
+        if let (Some(history), Some((pc, a_reg, d_reg))) = (&mut self.history, undo) {
+            history.push(JournalEntry {
+                pc,
+                a_reg,
+                d_reg,
+                write: self.last_write.map(|write| (write.addr, write.old)),
+                keyboard: keyboard_undo,
+            });
+        }
+        self.cycles += 1;
+        Ok(())
     }
 
-    fn compute(&self, comp: Comp) -> i16 {
-        match comp {
-            Comp::Zero => 0,
-            Comp::One => 1,
-            Comp::MinusOne => -1,
-            Comp::D => self.d_reg,
-            Comp::A => self.a_reg,
-            Comp::NotD => !self.d_reg,
-            Comp::NotA => !self.a_reg,
-            Comp::MinusD => -1 * self.d_reg,
-            Comp::MinusA => -1 * self.d_reg,
-            Comp::DPulsOne => self.d_reg + 1,
-            Comp::APulsOne => self.a_reg + 1,
-            Comp::DMinusOne => self.d_reg - 1,
-            Comp::AMinusOne => self.a_reg - 1,
-            Comp::DPulsA => self.d_reg + self.a_reg,
-            Comp::DMinusA => self.d_reg - self.a_reg,
-            Comp::AMinusD => self.a_reg - self.d_reg,
-            Comp::DAndA => self.d_reg & self.a_reg,
-            Comp::DOrA => self.d_reg | self.a_reg,
-            Comp::M => self.ram[self.a_reg as usize],
-            Comp::NotM => !self.ram[self.a_reg as usize],
-            Comp::MPlusOne => self.ram[self.a_reg as usize] + 1,
-            Comp::MMinusOne => self.ram[self.a_reg as usize] - 1,
-            Comp::DPulsM => self.d_reg + self.ram[self.a_reg as usize],
-            Comp::DMinusM => self.d_reg - self.ram[self.a_reg as usize],
-            Comp::MMinusD => self.ram[self.a_reg as usize] - self.d_reg,
-            Comp::DAndM => self.d_reg & self.ram[self.a_reg as usize],
-            Comp::DOrM => self.d_reg | self.ram[self.a_reg as usize],
+    /// M is read from and written to the address A had before the instruction and a jump goes there
+    /// as well, like the hardware where pc loads the old A on the same clock edge
+    #[inline(always)]
+    fn exec(&mut self, op: Op) {
+        self.last_read = None;
+        self.last_write = None;
+        match op {
+            Op::A(val) => {
+                self.a_reg = val;
+                self.pc += 1;
+            }
+            Op::C {
+                alu, dest, jump, ..
+            } => {
+                let old_a = self.a_reg;
+                let m_addr = old_a as u16 as usize & self.ram_mask;
+                let y = if alu.use_m {
+                    let m = self.bus.read(m_addr, self.cycles);
+                    self.last_read = Some(MemoryAccess {
+                        addr: m_addr,
+                        kind: AccessKind::Read,
+                        old: m,
+                        new: m,
+                    });
+                    m
+                } else {
+                    self.a_reg
+                };
+                let val = alu.eval(self.d_reg, y);
+
+                if dest & DEST_M != 0 {
+                    self.write_m(m_addr, val);
+                }
+                if dest & DEST_A != 0 {
+                    self.a_reg = val;
+                }
+                if dest & DEST_D != 0 {
+                    self.d_reg = val;
+                }
+
+                if jump & jump_cond(val) != 0 {
+                    self.pc = old_a as u16 as usize & self.rom_mask;
+                } else {
+                    self.pc += 1;
+                }
+            }
         }
     }
+
+    #[inline(always)]
+    fn write_m(&mut self, addr: usize, val: i16) {
+        self.last_write = Some(MemoryAccess {
+            addr,
+            kind: AccessKind::Write,
+            old: self.bus.peek(addr, self.cycles),
+            new: val,
+        });
+        self.bus.write(addr, val, self.cycles);
+    }
 }
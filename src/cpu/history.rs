@@ -0,0 +1,66 @@
+use std::collections::VecDeque;
+
+/// everything needed to undo one instruction
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct JournalEntry {
+    pub pc: usize,
+    pub a_reg: i16,
+    pub d_reg: i16,
+    /// address and old value of the word written to M
+    pub write: Option<(usize, i16)>,
+    /// old value of `KBD` and old position of the keyboard timeline
+    pub keyboard: Option<(i16, usize)>,
+}
+
+/// journal of the last `capacity` instructions
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct History {
+    journal: VecDeque<JournalEntry>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            journal: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, entry: JournalEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.journal.len() == self.capacity {
+            self.journal.pop_front();
+        }
+        self.journal.push_back(entry);
+    }
+
+    pub fn pop(&mut self) -> Option<JournalEntry> {
+        self.journal.pop_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.journal.len()
+    }
+}
+
+/// marks a point in time of a [`crate::cpu::HackCpu`], it is restored by undoing the journal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub(crate) cycles: usize,
+    pub(crate) pc: usize,
+    pub(crate) a_reg: i16,
+    pub(crate) d_reg: i16,
+}
+
+impl Snapshot {
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+}
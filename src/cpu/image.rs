@@ -0,0 +1,262 @@
+use std::fmt::Write;
+
+/// the text format of `.hack` files that [`crate::cpu::str2ml`] reads, one word of 16 binary digits per line
+pub fn ml2str(ml: &[u16]) -> String {
+    ml.iter().map(|word| format!("{:016b}\n", word)).collect()
+}
+
+/// a raw rom image, every word is two bytes with the high byte first
+pub fn ml2bin(ml: &[u16]) -> Vec<u8> {
+    ml.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+#[allow(clippy::manual_is_multiple_of)]
+pub fn bin2ml(bin: &[u8]) -> Result<Vec<u16>, String> {
+    if bin.len() % 2 != 0 {
+        return Err(format!(
+            "a rom image has two bytes per word but got {} bytes",
+            bin.len()
+        ));
+    }
+    Ok(bin
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect())
+}
+
+/// the largest image the readers accept, more than any hack rom
+const MAX_WORDS: usize = 0x10000;
+
+fn too_large(words: usize) -> String {
+    format!(
+        "the image reaches {} words but at most {} are supported",
+        words, MAX_WORDS
+    )
+}
+
+/// bytes per data record of [`ml2ihex`]
+const IHEX_RECORD_LEN: usize = 16;
+
+/// intel hex with byte addresses, every word is two bytes with the high byte first like [`ml2bin`].
+/// an extended linear address record is only written for images larger than 64 KiB
+pub fn ml2ihex(ml: &[u16]) -> String {
+    let bin = ml2bin(ml);
+    let mut hex = String::new();
+    let mut upper = 0;
+    for (i, chunk) in bin.chunks(IHEX_RECORD_LEN).enumerate() {
+        let addr = i * IHEX_RECORD_LEN;
+        if addr >> 16 != upper {
+            upper = addr >> 16;
+            ihex_record(&mut hex, 0, 0x04, &(upper as u16).to_be_bytes());
+        }
+        ihex_record(&mut hex, addr as u16, 0x00, chunk);
+    }
+    ihex_record(&mut hex, 0, 0x01, &[]);
+    hex
+}
+
+fn ihex_record(hex: &mut String, addr: u16, kind: u8, data: &[u8]) {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(addr.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend(data);
+    let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    bytes.push(sum.wrapping_neg());
+
+    hex.push(':');
+    for byte in bytes {
+        write!(hex, "{:02X}", byte).unwrap();
+    }
+    hex.push('\n');
+}
+
+/// reads the data, end of file and extended address records of intel hex with byte addresses.
+/// bytes that no record sets are 0
+#[allow(clippy::manual_div_ceil)]
+pub fn ihex2ml(hex: &str) -> Result<Vec<u16>, String> {
+    let mut bin = Vec::new();
+    let mut base = 0;
+    for (i, line) in hex.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let err = |msg: &str| format!("{} in line {}", msg, i + 1);
+        let digits = line
+            .strip_prefix(':')
+            .ok_or_else(|| err("a record has to start with `:`"))?;
+        if !digits.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return Err(err("a record has to consist of hex digits"));
+        }
+        if digits.len() % 2 != 0 || digits.len() < 10 {
+            return Err(err("a record is too short"));
+        }
+        let bytes: Vec<u8> = (0..digits.len())
+            .step_by(2)
+            .map(|j| u8::from_str_radix(&digits[j..j + 2], 16).unwrap())
+            .collect();
+        if bytes.len() != bytes[0] as usize + 5 {
+            return Err(err(
+                "the length of the record does not match its byte count",
+            ));
+        }
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(err("wrong checksum"));
+        }
+
+        let addr = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+        let data = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            0x00 => {
+                let start = base + addr;
+                if start + data.len() > 2 * MAX_WORDS {
+                    return Err(err(&too_large((start + data.len() + 1) / 2)));
+                }
+                if bin.len() < start + data.len() {
+                    bin.resize(start + data.len(), 0);
+                }
+                bin[start..start + data.len()].copy_from_slice(data);
+            }
+            0x01 => break,
+            0x02 | 0x04 if data.len() == 2 => {
+                let segment = u16::from_be_bytes([data[0], data[1]]) as usize;
+                base = if bytes[3] == 0x02 {
+                    segment << 4
+                } else {
+                    segment << 16
+                };
+            }
+            // start addresses do not matter for a rom image
+            0x03 | 0x05 => (),
+            kind => return Err(err(&format!("unsupported record type {:02X}", kind))),
+        }
+    }
+    if bin.len() % 2 != 0 {
+        bin.push(0);
+    }
+    bin2ml(&bin)
+}
+
+/// a memory file for verilog's `$readmemb`, one word per line
+pub fn ml2readmemb(ml: &[u16]) -> String {
+    ml2str(ml)
+}
+
+/// reads the binary words, `//` and `/* */` comments, `_` separators and `@addr` directives with a
+/// hex address of a verilog memory file. words that are not set are 0
+pub fn readmemb2ml(mem: &str) -> Result<Vec<u16>, String> {
+    let mut ml = Vec::new();
+    let mut addr = 0;
+    for (i, line) in strip_comments(mem).lines().enumerate() {
+        let err = |msg: String| format!("{} in line {}", msg, i + 1);
+        for word in line.split_whitespace() {
+            if let Some(hex) = word.strip_prefix('@') {
+                addr = usize::from_str_radix(&hex.replace('_', ""), 16)
+                    .map_err(|_| err(format!("`{}` is not a hex address", word)))?;
+                continue;
+            }
+            let val = u16::from_str_radix(&word.replace('_', ""), 2)
+                .map_err(|_| err(format!("`{}` is not a 16 bit binary word", word)))?;
+            if addr >= MAX_WORDS {
+                return Err(err(too_large(addr + 1)));
+            }
+            if ml.len() <= addr {
+                ml.resize(addr + 1, 0);
+            }
+            ml[addr] = val;
+            addr += 1;
+        }
+    }
+    Ok(ml)
+}
+
+/// replaces comments by a space, newlines inside block comments are kept so line numbers stay the same
+fn strip_comments(code: &str) -> String {
+    let mut out = String::new();
+    let mut rest = code;
+    while let Some(start) = rest.find('/') {
+        out.push_str(&rest[..start]);
+        let comment = &rest[start..];
+        let end = if comment.starts_with("//") {
+            comment.find('\n').unwrap_or(comment.len())
+        } else if comment.starts_with("/*") {
+            let end = comment.find("*/").map_or(comment.len(), |end| end + 2);
+            out.extend(comment[..end].matches('\n'));
+            end
+        } else {
+            out.push('/');
+            rest = &comment[1..];
+            continue;
+        };
+        out.push(' ');
+        rest = &comment[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+const LOGISIM_HEADER: &str = "v2.0 raw";
+
+/// words per line of [`ml2logisim`]
+const LOGISIM_LINE_LEN: usize = 8;
+
+/// a logisim rom image, hex words after a `v2.0 raw` header. runs of at least four equal words are
+/// written as `count*word` like logisim does
+pub fn ml2logisim(ml: &[u16]) -> String {
+    let mut items = Vec::new();
+    let mut i = 0;
+    while i < ml.len() {
+        let run = ml[i..].iter().take_while(|&&word| word == ml[i]).count();
+        if run >= 4 {
+            items.push(format!("{}*{:x}", run, ml[i]));
+            i += run;
+        } else {
+            items.push(format!("{:x}", ml[i]));
+            i += 1;
+        }
+    }
+
+    let mut text = String::from(LOGISIM_HEADER);
+    text.push('\n');
+    for line in items.chunks(LOGISIM_LINE_LEN) {
+        text.push_str(&line.join(" "));
+        text.push('\n');
+    }
+    text
+}
+
+/// reads a logisim `v2.0 raw` image, `#` starts a comment
+pub fn logisim2ml(image: &str) -> Result<Vec<u16>, String> {
+    let mut lines = image.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim() == LOGISIM_HEADER => (),
+        _ => {
+            return Err(format!(
+                "a logisim image has to start with `{}`",
+                LOGISIM_HEADER
+            ))
+        }
+    }
+
+    let mut ml = Vec::new();
+    for (i, line) in lines {
+        let line = line.split('#').next().unwrap();
+        for item in line.split_whitespace() {
+            let err = || format!("`{}` is not a 16 bit hex word in line {}", item, i + 1);
+            let (count, word) = match item.split_once('*') {
+                Some((count, word)) => (count.parse::<usize>().map_err(|_| err())?, word),
+                None => (1, item),
+            };
+            let word = u16::from_str_radix(word, 16).map_err(|_| err())?;
+            if count > MAX_WORDS - ml.len() {
+                return Err(format!(
+                    "{} in line {}",
+                    too_large(ml.len().saturating_add(count)),
+                    i + 1
+                ));
+            }
+            ml.resize(ml.len() + count, word);
+        }
+    }
+    Ok(ml)
+}
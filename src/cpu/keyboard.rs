@@ -0,0 +1,155 @@
+/// a key of the hack keyboard, see figure 5.6 of the book for the key codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// printable ascii characters use their ascii code, other characters have none
+    Char(char),
+    Newline,
+    Backspace,
+    Left,
+    Up,
+    Right,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    Esc,
+    /// function keys F1 to F12, other numbers have no code
+    F(u8),
+}
+
+impl Key {
+    /// the code in the `KBD` register, 0 for keys the hack character set does not have
+    pub fn code(&self) -> i16 {
+        match *self {
+            Key::Char(c @ ' '..='~') => c as i16,
+            Key::Char(_) => 0,
+            Key::Newline => 128,
+            Key::Backspace => 129,
+            Key::Left => 130,
+            Key::Up => 131,
+            Key::Right => 132,
+            Key::Down => 133,
+            Key::Home => 134,
+            Key::End => 135,
+            Key::PageUp => 136,
+            Key::PageDown => 137,
+            Key::Insert => 138,
+            Key::Delete => 139,
+            Key::Esc => 140,
+            Key::F(n @ 1..=12) => 140 + n as i16,
+            Key::F(_) => 0,
+        }
+    }
+
+    pub fn from_code(code: i16) -> Option<Key> {
+        match code {
+            32..=126 => Some(Key::Char(code as u8 as char)),
+            128 => Some(Key::Newline),
+            129 => Some(Key::Backspace),
+            130 => Some(Key::Left),
+            131 => Some(Key::Up),
+            132 => Some(Key::Right),
+            133 => Some(Key::Down),
+            134 => Some(Key::Home),
+            135 => Some(Key::End),
+            136 => Some(Key::PageUp),
+            137 => Some(Key::PageDown),
+            138 => Some(Key::Insert),
+            139 => Some(Key::Delete),
+            140 => Some(Key::Esc),
+            141..=152 => Some(Key::F((code - 140) as u8)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+    Press(Key),
+    /// releases whatever key is held, the hack keyboard only knows one key at a time
+    Release,
+}
+
+/// a timeline of key events that drives the `KBD` register, cycles count from the creation of the cpu
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Keyboard {
+    events: Vec<(usize, KeyEvent)>,
+    next: usize,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn event(&mut self, cycle: usize, event: KeyEvent) -> &mut Self {
+        // keep the events sorted, events at the same cycle stay in insertion order
+        let i = self.events.partition_point(|&(c, _)| c <= cycle);
+        self.events.insert(i, (cycle, event));
+        self
+    }
+
+    pub fn press(&mut self, cycle: usize, key: Key) -> &mut Self {
+        self.event(cycle, KeyEvent::Press(key))
+    }
+
+    pub fn release(&mut self, cycle: usize) -> &mut Self {
+        self.event(cycle, KeyEvent::Release)
+    }
+
+    /// presses `key` at `cycle` and releases it `hold` cycles later
+    pub fn tap(&mut self, cycle: usize, key: Key, hold: usize) -> &mut Self {
+        self.press(cycle, key).release(cycle + hold)
+    }
+
+    /// types `text` starting at `cycle`, every key is held for `hold` cycles followed by a pause of `hold` cycles.
+    /// `\n` is typed as [`Key::Newline`]
+    pub fn type_str(&mut self, cycle: usize, text: &str, hold: usize) -> &mut Self {
+        for (i, c) in text.chars().enumerate() {
+            let key = if c == '\n' {
+                Key::Newline
+            } else {
+                Key::Char(c)
+            };
+            self.tap(cycle + 2 * i * hold, key, hold);
+        }
+        self
+    }
+
+    pub fn events(&self) -> &[(usize, KeyEvent)] {
+        &self.events
+    }
+
+    pub(crate) fn position(&self) -> usize {
+        self.next
+    }
+
+    pub(crate) fn set_position(&mut self, next: usize) {
+        self.next = next;
+    }
+
+    /// the cycle of the next event that has not been polled yet
+    pub(crate) fn next_cycle(&self) -> Option<usize> {
+        self.events.get(self.next).map(|&(cycle, _)| cycle)
+    }
+
+    /// the key code the register has to show after all events up to `cycle` happend,
+    /// `None` if no new event happend since the last call
+    pub(crate) fn poll(&mut self, cycle: usize) -> Option<i16> {
+        let mut code = None;
+        while let Some(&(c, event)) = self.events.get(self.next) {
+            if c > cycle {
+                break;
+            }
+            code = Some(match event {
+                KeyEvent::Press(key) => key.code(),
+                KeyEvent::Release => 0,
+            });
+            self.next += 1;
+        }
+        code
+    }
+}
@@ -0,0 +1,67 @@
+use super::{asm2ml, AsmProgram};
+use std::fmt::Write;
+
+/// a listing of `program`, which was assembled from `code`: one row per rom address with the machine
+/// word in binary and hex, the labels defined there and the source line, followed by the label and
+/// variable tables. without a source map the rows show the instruction instead of the line
+pub fn listing(program: &AsmProgram, code: &str) -> String {
+    let lines: Vec<&str> = code.lines().collect();
+    let ml = asm2ml(program.instructions.clone());
+    let labels: Vec<String> = (0..ml.len())
+        .map(|addr| program.labels_at(addr).join(", "))
+        .collect();
+    let width = labels.iter().map(String::len).max().unwrap_or(0).max(5);
+
+    let mut text = format!(
+        "{:>5}  {:<16}  {:<4}  {:>5}  {:<width$}  source\n",
+        "rom",
+        "binary",
+        "hex",
+        "line",
+        "label",
+        width = width
+    );
+    for (addr, word) in ml.iter().enumerate() {
+        let (line, source) = match program.source_pos(addr) {
+            Some(pos) => (
+                pos.line.to_string(),
+                lines
+                    .get(pos.line - 1)
+                    .map_or("", |line| line.trim())
+                    .to_string(),
+            ),
+            None => (String::from("-"), program.instructions[addr].to_string()),
+        };
+        writeln!(
+            text,
+            "{:>5}  {:016b}  {:04X}  {:>5}  {:<width$}  {}",
+            addr,
+            word,
+            word,
+            line,
+            labels[addr],
+            source,
+            width = width
+        )
+        .unwrap();
+    }
+    // labels after the last instruction
+    let end = program.labels_at(ml.len());
+    if !end.is_empty() {
+        writeln!(text, "{:>5}  {:29}  {}", ml.len(), "", end.join(", ")).unwrap();
+    }
+
+    for (title, table) in [
+        ("labels", &program.labels),
+        ("variables", &program.variables),
+    ] {
+        write!(text, "\n{}\n", title).unwrap();
+        let mut entries: Vec<(&String, &usize)> = table.iter().collect();
+        entries.sort_by_key(|&(name, addr)| (addr, name));
+        let width = table.keys().map(String::len).max().unwrap_or(0);
+        for (name, addr) in entries {
+            writeln!(text, "  {:<width$}  {:>5}", name, addr, width = width).unwrap();
+        }
+    }
+    text
+}
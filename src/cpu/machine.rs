@@ -0,0 +1,82 @@
+use super::SCREEN_WORDS;
+
+/// the memory map and rom size of a hack computer. [`MachineProfile::default`] is the official one,
+/// others can be used with [`crate::cpu::HackCpu::with_machine`], [`crate::cpu::assemble_for`] and
+/// [`crate::vm::vm2asm_for`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineProfile {
+    pub name: String,
+    /// words of ram, a power of two up to 0x10000. addresses are the low bits of A
+    pub ram_size: usize,
+    /// words of rom, a power of two up to 0x10000. jump targets are the low bits of A
+    pub rom_size: usize,
+    /// first word of the 512x256 screen
+    pub screen: usize,
+    pub keyboard: usize,
+    /// first address of the assembler's variables and the static segment of the vm
+    pub statics: usize,
+    /// first of the 8 registers of the temp segment of the vm
+    pub temp: usize,
+}
+
+impl MachineProfile {
+    /// the computer of nand2tetris with 32K words of ram and rom
+    pub fn hack() -> Self {
+        Self {
+            name: String::from("hack"),
+            ram_size: 0x8000,
+            rom_size: 0x8000,
+            screen: crate::SCREEN,
+            keyboard: crate::KBD,
+            statics: crate::STATIC,
+            temp: crate::TEMP,
+        }
+    }
+
+    /// the official memory map with 64K words of ram and rom, the 16 bits of A address all of them
+    pub fn extended() -> Self {
+        Self {
+            name: String::from("hack-64k"),
+            ram_size: 0x10000,
+            rom_size: 0x10000,
+            ..Self::hack()
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (memory, size) in [("ram", self.ram_size), ("rom", self.rom_size)] {
+            if !size.is_power_of_two() || size > 0x10000 {
+                return Err(format!(
+                    "the {} of {} has {} words but needs a power of two up to 65536",
+                    memory, self.name, size
+                ));
+            }
+        }
+        let screen = self.screen..self.screen + SCREEN_WORDS;
+        if screen.end > self.ram_size || self.keyboard >= self.ram_size {
+            return Err(format!(
+                "the screen and keyboard of {} have to be inside the {} words of ram",
+                self.name, self.ram_size
+            ));
+        }
+        if screen.contains(&self.keyboard) {
+            return Err(format!(
+                "the keyboard of {} at {} is inside the screen at {}",
+                self.name, self.keyboard, self.screen
+            ));
+        }
+        if self.temp + 8 > self.statics || self.statics >= self.ram_size {
+            return Err(format!(
+                "the temp segment of {} at {} has to be below the statics at {}",
+                self.name, self.temp, self.statics
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for MachineProfile {
+    fn default() -> Self {
+        Self::hack()
+    }
+}
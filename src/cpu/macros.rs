@@ -0,0 +1,387 @@
+use super::{assemble, AsmProgram};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+/// where a line of expanded code comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// the name used in `.include`, empty for the code passed to [`Preprocessor::expand`]
+    pub file: String,
+    pub line: usize,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.file.is_empty() {
+            write!(f, "{}", self.line)
+        } else {
+            write!(f, "{}:{}", self.file, self.line)
+        }
+    }
+}
+
+/// plain hack assembly produced by [`Preprocessor::expand`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub code: String,
+    /// the origin of every line of `code`
+    pub origins: Vec<Origin>,
+}
+
+impl Expansion {
+    /// the origin of a line of `code`, lines count from 1
+    pub fn origin(&self, line: usize) -> Option<&Origin> {
+        self.origins.get(line.checked_sub(1)?)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<(String, Origin)>,
+}
+
+/// expands the macro extensions of the assembler into plain hack assembly. they are opt-in,
+/// [`crate::cpu::parse`] and [`crate::cpu::assemble`] only accept plain assembly.
+///
+/// - `.equ NAME value` defines a constant, `@NAME` loads it. values are decimal, `0x` or `0b` numbers
+/// - `.include "file.asm"` inserts a file, see [`Preprocessor::base_dir`] and [`Preprocessor::file`]
+/// - `.macro NAME a, b` ... `.endm` defines a macro, `%a` in the body is replaced by the argument
+///   and `%%` by a suffix unique to every expansion, so labels like `(LOOP%%)` do not clash.
+///   it is used as `NAME x, y`
+/// - pseudo-instructions: `LOADD value` loads any 16 bit value into D, hex and
+///   binary values above 0x7fff are taken as their bits, `PUSHD` and `POPD` push and pop
+///   D on the stack at `SP`, `GOTO label` jumps unconditionally and `HALT` ends the program
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessor {
+    base_dir: Option<PathBuf>,
+    files: HashMap<String, String>,
+}
+
+/// how deep includes and macros may nest before the expansion is considered recursive
+const MAX_DEPTH: usize = 64;
+
+struct State {
+    constants: HashMap<String, i32>,
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+    out: Vec<(String, Origin)>,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `.include` reads files relative to `dir`
+    pub fn base_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.base_dir = Some(dir.into());
+        self
+    }
+
+    /// makes `code` available to `.include` as `name` without touching the file system
+    pub fn file(&mut self, name: &str, code: &str) -> &mut Self {
+        self.files.insert(name.to_string(), code.to_string());
+        self
+    }
+
+    /// errors are prefixed with the file and line they happen in
+    pub fn expand(&self, code: &str) -> Result<Expansion, String> {
+        let mut state = State {
+            constants: HashMap::new(),
+            macros: HashMap::new(),
+            expansions: 0,
+            out: Vec::new(),
+        };
+        let lines = numbered("", code);
+        self.expand_lines(&mut state, &lines, &mut vec![String::new()], 0)?;
+
+        let mut code = String::new();
+        let mut origins = Vec::new();
+        for (line, origin) in state.out {
+            code.push_str(&line);
+            code.push('\n');
+            origins.push(origin);
+        }
+        Ok(Expansion { code, origins })
+    }
+
+    fn read(&self, name: &str) -> Result<String, String> {
+        if let Some(code) = self.files.get(name) {
+            return Ok(code.clone());
+        }
+        let path = match &self.base_dir {
+            Some(dir) => dir.join(name),
+            None => PathBuf::from(name),
+        };
+        std::fs::read_to_string(&path)
+            .map_err(|e| format!("cannot include {}: {}", path.display(), e))
+    }
+
+    fn expand_lines(
+        &self,
+        state: &mut State,
+        lines: &[(String, Origin)],
+        includes: &mut Vec<String>,
+        depth: usize,
+    ) -> Result<(), String> {
+        if depth > MAX_DEPTH {
+            let origin = lines.first().map_or(String::new(), |(_, o)| o.to_string());
+            return Err(format!(
+                "{}: macros or includes are nested more than {} levels deep",
+                origin, MAX_DEPTH
+            ));
+        }
+
+        let mut i = 0;
+        while let Some((line, origin)) = lines.get(i) {
+            i += 1;
+            let err = |msg: String| format!("{}: {}", origin, msg);
+            let (content, comment) = match line.find("//") {
+                Some(at) => line.split_at(at),
+                None => (line.as_str(), ""),
+            };
+            let content = content.trim();
+            let (word, rest) = match content.split_once(char::is_whitespace) {
+                Some((word, rest)) => (word, rest.trim()),
+                None => (content, ""),
+            };
+
+            match word {
+                ".equ" => {
+                    let (name, value) = rest
+                        .split_once(char::is_whitespace)
+                        .ok_or_else(|| err(String::from("expected `.equ NAME value`")))?;
+                    let value = self.value(state, value.trim()).map_err(err)?;
+                    if state.constants.insert(name.to_string(), value).is_some() {
+                        return Err(err(format!("the constant {} is already defined", name)));
+                    }
+                }
+                ".include" => {
+                    let name = rest
+                        .strip_prefix('"')
+                        .and_then(|r| r.strip_suffix('"'))
+                        .ok_or_else(|| err(String::from("expected `.include \"file\"`")))?;
+                    if includes.iter().any(|file| file == name) {
+                        return Err(err(format!("{} includes itself", name)));
+                    }
+                    let code = self.read(name).map_err(err)?;
+                    includes.push(name.to_string());
+                    self.expand_lines(state, &numbered(name, &code), includes, depth + 1)?;
+                    includes.pop();
+                }
+                ".macro" => {
+                    let (name, params) = match rest.split_once(char::is_whitespace) {
+                        Some((name, params)) => (name, split_args(params)),
+                        None => (rest, Vec::new()),
+                    };
+                    if name.is_empty() {
+                        return Err(err(String::from("expected `.macro NAME params`")));
+                    }
+                    let start = i;
+                    let end = lines[start..]
+                        .iter()
+                        .position(|(line, _)| directive(line) == ".endm")
+                        .map(|len| start + len)
+                        .ok_or_else(|| err(format!("the macro {} has no `.endm`", name)))?;
+                    if let Some((_, o)) = lines[start..end]
+                        .iter()
+                        .find(|(line, _)| directive(line) == ".macro")
+                    {
+                        return Err(format!("{}: macros cannot be defined inside a macro", o));
+                    }
+                    let params = params.iter().map(|param| param.to_string()).collect();
+                    let body = lines[start..end].to_vec();
+                    state
+                        .macros
+                        .insert(name.to_string(), Macro { params, body });
+                    i = end + 1;
+                }
+                ".endm" => return Err(err(String::from("`.endm` without `.macro`"))),
+                _ if state.macros.contains_key(word) => {
+                    let mac = state.macros[word].clone();
+                    let args = split_args(rest);
+                    if args.len() != mac.params.len() {
+                        return Err(err(format!(
+                            "the macro {} takes {} arguments but got {}",
+                            word,
+                            mac.params.len(),
+                            args.len()
+                        )));
+                    }
+                    state.expansions += 1;
+                    let suffix = format!("${}", state.expansions);
+                    let mut params: Vec<(&String, &str)> = mac.params.iter().zip(args).collect();
+                    // `%ab` must not be replaced by the argument of `%a`
+                    params.sort_by_key(|(param, _)| std::cmp::Reverse(param.len()));
+                    let body: Vec<(String, Origin)> = mac
+                        .body
+                        .iter()
+                        .map(|(line, origin)| {
+                            let mut line = line.replace("%%", &suffix);
+                            for (param, arg) in &params {
+                                line = line.replace(&format!("%{}", param), arg);
+                            }
+                            (line, origin.clone())
+                        })
+                        .collect();
+                    self.expand_lines(state, &body, includes, depth + 1)?;
+                }
+                "LOADD" | "PUSHD" | "POPD" | "GOTO" | "HALT" => {
+                    let code = self.pseudo(state, word, rest).map_err(err)?;
+                    state.expansions += 1;
+                    let code = code.replace("%%", &format!("${}", state.expansions));
+                    for line in code.lines() {
+                        state.out.push((line.to_string(), origin.clone()));
+                    }
+                }
+                _ => {
+                    let substituted = self.substitute(state, content).map_err(err)?;
+                    let line = if substituted == content {
+                        line.clone()
+                    } else {
+                        let indent = &line[..line.len() - line.trim_start().len()];
+                        format!("{}{} {}", indent, substituted, comment)
+                            .trim_end()
+                            .to_string()
+                    };
+                    state.out.push((line, origin.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// a number or a constant
+    fn value(&self, state: &State, text: &str) -> Result<i32, String> {
+        if let Some(&value) = state.constants.get(text) {
+            return Ok(value);
+        }
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let parsed = if let Some(hex) = digits.strip_prefix("0x") {
+            i32::from_str_radix(hex, 16)
+        } else if let Some(bin) = digits.strip_prefix("0b") {
+            i32::from_str_radix(bin, 2)
+        } else {
+            digits.parse()
+        };
+        let value = match parsed {
+            Ok(value) if negative => -value,
+            // hex and binary literals up to 0xffff are the bits of a negative value
+            Ok(value) if digits.starts_with("0x") || digits.starts_with("0b") => match value {
+                0x8000..=0xffff => value - 0x10000,
+                _ => value,
+            },
+            Ok(value) => value,
+            Err(_) => return Err(format!("`{}` is neither a number nor a constant", text)),
+        };
+        if !(i16::MIN as i32..=i16::MAX as i32).contains(&value) {
+            return Err(format!("{} does not fit into 16 bits", text));
+        }
+        Ok(value)
+    }
+
+    /// replaces `@CONSTANT` by its value
+    fn substitute(&self, state: &State, content: &str) -> Result<String, String> {
+        let name = match content.strip_prefix('@') {
+            Some(name) => name,
+            None => return Ok(content.to_string()),
+        };
+        match state.constants.get(name) {
+            Some(&value) if value < 0 => Err(format!(
+                "the constant {} is negative and cannot be loaded into A, use LOADD",
+                name
+            )),
+            Some(&value) => Ok(format!("@{}", value)),
+            None => Ok(content.to_string()),
+        }
+    }
+
+    fn pseudo(&self, state: &State, word: &str, rest: &str) -> Result<String, String> {
+        let args = split_args(rest);
+        let expected = if matches!(word, "LOADD" | "GOTO") {
+            1
+        } else {
+            0
+        };
+        if args.len() != expected {
+            return Err(format!(
+                "{} takes {} arguments but got {}",
+                word,
+                expected,
+                args.len()
+            ));
+        }
+
+        Ok(match word {
+            "LOADD" => match self.value(state, args[0]) {
+                Ok(0) => String::from("D=0"),
+                Ok(1) => String::from("D=1"),
+                Ok(-1) => String::from("D=-1"),
+                Ok(value) if value >= 0 => format!("@{}\nD=A", value),
+                Ok(value) if value == i16::MIN as i32 => String::from("@32767\nD=-A\nD=D-1"),
+                Ok(value) => format!("@{}\nD=-A", -value),
+                // a label or variable, hack symbols cannot start with a digit
+                Err(_) if !args[0].starts_with(|c: char| c.is_ascii_digit() || c == '-') => {
+                    format!("@{}\nD=A", args[0])
+                }
+                Err(err) => return Err(err),
+            },
+            "PUSHD" => String::from("@SP\nAM=M+1\nA=A-1\nM=D"),
+            "POPD" => String::from("@SP\nAM=M-1\nD=M"),
+            "GOTO" => format!("@{}\n0;JMP", args[0]),
+            _ => String::from("(HALT%%)\n@HALT%%\n0;JMP"),
+        })
+    }
+}
+
+/// expands `code` with `preprocessor` and assembles the result, errors point into the original files
+pub fn assemble_macros(
+    code: &str,
+    preprocessor: &Preprocessor,
+) -> Result<(AsmProgram, Expansion), String> {
+    let expansion = preprocessor.expand(code)?;
+    let assembly = assemble(&expansion.code);
+    match assembly.program {
+        Some(program) => Ok((program, expansion)),
+        None => {
+            let error = assembly.errors().next().unwrap();
+            let origin = expansion.origin(error.pos.line).unwrap();
+            Err(format!("{}: {}", origin, error.message))
+        }
+    }
+}
+
+fn numbered(file: &str, code: &str) -> Vec<(String, Origin)> {
+    code.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let origin = Origin {
+                file: file.to_string(),
+                line: i + 1,
+            };
+            (line.to_string(), origin)
+        })
+        .collect()
+}
+
+/// the first word of a line if it is a directive
+fn directive(line: &str) -> &str {
+    let word = line.split_whitespace().next().unwrap_or("");
+    if word.starts_with('.') {
+        word
+    } else {
+        ""
+    }
+}
+
+fn split_args(args: &str) -> Vec<&str> {
+    args.split(',')
+        .map(str::trim)
+        .filter(|arg| !arg.is_empty())
+        .collect()
+}
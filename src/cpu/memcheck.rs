@@ -0,0 +1,155 @@
+use super::device::Bus;
+use super::{AccessKind, AsmProgram, MachineProfile, MemoryAccess};
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MemIssueKind {
+    /// a ram word was read before anything wrote to it
+    UninitializedRead,
+    /// the keyboard register is input only
+    KeyboardWrite,
+    /// an access above the keyboard register that no device is mapped at
+    UnmappedRead,
+    UnmappedWrite,
+}
+
+/// a suspicious access of the instruction at `pc`, repeated ones are counted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemIssue {
+    pub kind: MemIssueKind,
+    pub pc: usize,
+    pub addr: usize,
+    /// cycle of the first occurrence
+    pub cycle: usize,
+    pub count: usize,
+}
+
+impl fmt::Display for MemIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            MemIssueKind::UninitializedRead => {
+                write!(f, "read of {} which was never written", self.addr)
+            }
+            MemIssueKind::KeyboardWrite => write!(f, "write to the keyboard at {}", self.addr),
+            MemIssueKind::UnmappedRead => write!(f, "read of the unmapped address {}", self.addr),
+            MemIssueKind::UnmappedWrite => {
+                write!(f, "write to the unmapped address {}", self.addr)
+            }
+        }
+    }
+}
+
+/// shadow memory collected by [`crate::cpu::HackCpu::enable_memcheck`]. it knows which words were
+/// written by the program or with [`crate::cpu::HackCpu::set_ram`], the screen, the keyboard and
+/// devices count as initialized
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemCheck {
+    written: Vec<bool>,
+    issues: Vec<MemIssue>,
+    /// index into `issues` by kind, pc and address
+    index: HashMap<(MemIssueKind, usize, usize), usize>,
+}
+
+impl MemCheck {
+    pub fn new(ram_size: usize) -> Self {
+        Self {
+            written: vec![false; ram_size],
+            issues: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    /// true if the word at `addr` was written since the check was enabled
+    pub fn is_written(&self, addr: usize) -> bool {
+        self.written.get(addr).copied().unwrap_or(false)
+    }
+
+    pub(crate) fn mark_written(&mut self, addr: usize) {
+        if let Some(written) = self.written.get_mut(addr) {
+            *written = true;
+        }
+    }
+
+    /// checks the accesses of the instruction at `pc`
+    pub(crate) fn record(
+        &mut self,
+        pc: usize,
+        cycle: usize,
+        accesses: [Option<MemoryAccess>; 2],
+        machine: &MachineProfile,
+        bus: &Bus,
+    ) {
+        for access in accesses.into_iter().flatten() {
+            let addr = access.addr;
+            let kind = if bus.is_mapped(addr) {
+                None
+            } else if addr > machine.keyboard {
+                Some(match access.kind {
+                    AccessKind::Read => MemIssueKind::UnmappedRead,
+                    AccessKind::Write => MemIssueKind::UnmappedWrite,
+                })
+            } else if addr == machine.keyboard {
+                (access.kind == AccessKind::Write).then_some(MemIssueKind::KeyboardWrite)
+            } else if addr >= machine.screen {
+                None
+            } else if access.kind == AccessKind::Read {
+                (!self.written[addr]).then_some(MemIssueKind::UninitializedRead)
+            } else {
+                self.written[addr] = true;
+                None
+            };
+            if let Some(kind) = kind {
+                self.add(kind, pc, addr, cycle);
+            }
+        }
+    }
+
+    fn add(&mut self, kind: MemIssueKind, pc: usize, addr: usize, cycle: usize) {
+        match self.index.get(&(kind, pc, addr)) {
+            Some(&i) => self.issues[i].count += 1,
+            None => {
+                self.index.insert((kind, pc, addr), self.issues.len());
+                self.issues.push(MemIssue {
+                    kind,
+                    pc,
+                    addr,
+                    cycle,
+                    count: 1,
+                });
+            }
+        }
+    }
+
+    /// the issues in the order they first occurred
+    pub fn issues(&self) -> &[MemIssue] {
+        &self.issues
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// one line per issue with the source line of the instruction in `program`
+    pub fn report(&self, program: &AsmProgram) -> String {
+        let mut text = String::new();
+        for issue in &self.issues {
+            let line = program
+                .source_pos(issue.pc)
+                .map_or(String::from("-"), |pos| pos.line.to_string());
+            write!(text, "line {}, pc {}: {}", line, issue.pc, issue).unwrap();
+            if issue.count > 1 {
+                write!(
+                    text,
+                    " ({} times, first at cycle {})",
+                    issue.count, issue.cycle
+                )
+                .unwrap();
+            } else {
+                write!(text, " (at cycle {})", issue.cycle).unwrap();
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
@@ -1,75 +1,214 @@
+mod alu;
+mod coverage;
+mod cst;
+mod debug;
+mod decode;
+mod device;
+mod diagnostic;
+mod disasm;
 mod hack_cpu;
+mod history;
+mod image;
+mod keyboard;
+mod listing;
+mod machine;
+mod macros;
+mod memcheck;
+mod object;
+mod optimize;
 mod parser;
+mod profile;
+mod program;
+mod screen;
+mod trace;
 
-pub use hack_cpu::HackCpu;
-pub use parser::{asm2ml, ml2asm, parse, str2ml};
+pub use alu::alu;
+pub use coverage::Coverage;
+pub use cst::{format_asm, SyntaxKind, SyntaxNode, SyntaxTree};
+pub use debug::{AccessKind, Breakpoint, Cmp, Condition, MemoryAccess, Register, Watchpoint};
+pub use device::{ConsoleOut, CycleCounter, Device, DeviceClone, Random};
+pub use diagnostic::{Assembly, Diagnostic, Severity};
+pub use disasm::disassemble;
+pub use hack_cpu::{CpuError, HackCpu, RunOutcome};
+pub use history::Snapshot;
+pub use image::{
+    bin2ml, ihex2ml, logisim2ml, ml2bin, ml2ihex, ml2logisim, ml2readmemb, ml2str, readmemb2ml,
+};
+pub use keyboard::{Key, KeyEvent, Keyboard};
+pub use listing::listing;
+pub use machine::MachineProfile;
+pub use macros::{assemble_macros, Expansion, Origin, Preprocessor};
+pub use memcheck::{MemCheck, MemIssue, MemIssueKind};
+pub use object::{assemble_object, assemble_object_for, link, link_for, Linked, Object, Section};
+pub use optimize::{optimize, Optimized, Rewrite};
+pub use parser::{
+    asm2ml, assemble, assemble_for, ml2asm, parse, parse_program, parse_program_for,
+    parse_with_labels, str2ml,
+};
+pub use profile::{HotLoop, LabelTotal, Profile};
+pub use program::{AsmProgram, SourcePos};
+pub use screen::{Screen, SCREEN_HEIGHT, SCREEN_WIDTH, SCREEN_WORDS};
+pub use trace::{Trace, TraceEntry};
 
-#[repr(u8)]
-#[derive(Debug, Clone, PartialEq)]
-pub enum Comp {
+use std::fmt;
+
+/// the control bits `a zx nx zy ny f no` of a c-instruction, all 128 combinations are valid.
+/// the documented mnemonics are available as associated constants
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Comp(u8);
+
+#[allow(non_upper_case_globals)]
+impl Comp {
     // a = 0
-    Zero = 0b0101010,
-    One = 0b0111111,
-    MinusOne = 0b0111010,
-    D = 0b0001100,
-    A = 0b0110000,
-    NotD = 0b0001101,
-    NotA = 0b0110001,
-    MinusD = 0b0001111,
-    MinusA = 0b0110011,
-    DPulsOne = 0b0011111,
-    APulsOne = 0b0110111,
-    DMinusOne = 0b0110010, // is swapt to pass pong ???
-    AMinusOne = 0b0001110, //is swapt to pass pong ???
-    DPulsA = 0b0000010,
-    DMinusA = 0b0010011,
-    AMinusD = 0b0000111,
-    DAndA = 0b0000000,
-    DOrA = 0b0010101,
+    pub const Zero: Comp = Comp(0b0101010);
+    pub const One: Comp = Comp(0b0111111);
+    pub const MinusOne: Comp = Comp(0b0111010);
+    pub const D: Comp = Comp(0b0001100);
+    pub const A: Comp = Comp(0b0110000);
+    pub const NotD: Comp = Comp(0b0001101);
+    pub const NotA: Comp = Comp(0b0110001);
+    pub const MinusD: Comp = Comp(0b0001111);
+    pub const MinusA: Comp = Comp(0b0110011);
+    pub const DPulsOne: Comp = Comp(0b0011111);
+    pub const APulsOne: Comp = Comp(0b0110111);
+    pub const DMinusOne: Comp = Comp(0b0001110);
+    pub const AMinusOne: Comp = Comp(0b0110010);
+    pub const DPulsA: Comp = Comp(0b0000010);
+    pub const DMinusA: Comp = Comp(0b0010011);
+    pub const AMinusD: Comp = Comp(0b0000111);
+    pub const DAndA: Comp = Comp(0b0000000);
+    pub const DOrA: Comp = Comp(0b0010101);
     // a = 1
-    M = 0b1110000,
-    NotM = 0b1110001,
-    MPlusOne = 0b1110111,
-    MMinusOne = 0b1110010,
-    DPulsM = 0b1000010,
-    DMinusM = 0b1010011,
-    MMinusD = 0b1000111,
-    DAndM = 0b1000000,
-    DOrM = 0b1010101,
+    pub const M: Comp = Comp(0b1110000);
+    pub const NotM: Comp = Comp(0b1110001);
+    pub const MinusM: Comp = Comp(0b1110011);
+    pub const MPlusOne: Comp = Comp(0b1110111);
+    pub const MMinusOne: Comp = Comp(0b1110010);
+    pub const DPulsM: Comp = Comp(0b1000010);
+    pub const DMinusM: Comp = Comp(0b1010011);
+    pub const MMinusD: Comp = Comp(0b1000111);
+    pub const DAndM: Comp = Comp(0b1000000);
+    pub const DOrM: Comp = Comp(0b1010101);
+
+    const MNEMONICS: [(Comp, &'static str); 28] = [
+        (Comp::Zero, "0"),
+        (Comp::One, "1"),
+        (Comp::MinusOne, "-1"),
+        (Comp::D, "D"),
+        (Comp::A, "A"),
+        (Comp::NotD, "!D"),
+        (Comp::NotA, "!A"),
+        (Comp::MinusD, "-D"),
+        (Comp::MinusA, "-A"),
+        (Comp::DPulsOne, "D+1"),
+        (Comp::APulsOne, "A+1"),
+        (Comp::DMinusOne, "D-1"),
+        (Comp::AMinusOne, "A-1"),
+        (Comp::DPulsA, "D+A"),
+        (Comp::DMinusA, "D-A"),
+        (Comp::AMinusD, "A-D"),
+        (Comp::DAndA, "D&A"),
+        (Comp::DOrA, "D|A"),
+        (Comp::M, "M"),
+        (Comp::NotM, "!M"),
+        (Comp::MinusM, "-M"),
+        (Comp::MPlusOne, "M+1"),
+        (Comp::MMinusOne, "M-1"),
+        (Comp::DPulsM, "D+M"),
+        (Comp::DMinusM, "D-M"),
+        (Comp::MMinusD, "M-D"),
+        (Comp::DAndM, "D&M"),
+        (Comp::DOrM, "D|M"),
+    ];
+
+    /// only the lower 7 bits of `bits` are used
+    pub fn from_bits(bits: u8) -> Self {
+        Comp(bits & 0b1111111)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// the documented assembly mnemonic, `None` for the 100 undocumented encodings
+    pub fn mnemonic(&self) -> Option<&'static str> {
+        Comp::MNEMONICS
+            .iter()
+            .find(|(comp, _)| comp == self)
+            .map(|&(_, mnemonic)| mnemonic)
+    }
+
+    /// if set the alu reads M instead of A
+    pub fn a(&self) -> bool {
+        self.0 & 0b1000000 != 0
+    }
+
+    pub fn zx(&self) -> bool {
+        self.0 & 0b0100000 != 0
+    }
+
+    pub fn nx(&self) -> bool {
+        self.0 & 0b0010000 != 0
+    }
+
+    pub fn zy(&self) -> bool {
+        self.0 & 0b0001000 != 0
+    }
+
+    pub fn ny(&self) -> bool {
+        self.0 & 0b0000100 != 0
+    }
+
+    pub fn f(&self) -> bool {
+        self.0 & 0b0000010 != 0
+    }
+
+    pub fn no(&self) -> bool {
+        self.0 & 0b0000001 != 0
+    }
+
+    /// evaluates the alu with `d` as x and `a` or `m` as y depending on the a bit
+    pub fn eval(&self, d: i16, a: i16, m: i16) -> i16 {
+        let y = if self.a() { m } else { a };
+        alu(
+            d,
+            y,
+            self.zx(),
+            self.nx(),
+            self.zy(),
+            self.ny(),
+            self.f(),
+            self.no(),
+        )
+    }
+}
+
+impl fmt::Debug for Comp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mnemonic() {
+            Some(mnemonic) => write!(f, "Comp({})", mnemonic),
+            None => write!(f, "Comp({:#09b})", self.0),
+        }
+    }
+}
+
+/// the mnemonic, undocumented encodings are written as their 7 bits
+impl fmt::Display for Comp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mnemonic() {
+            Some(mnemonic) => write!(f, "{}", mnemonic),
+            None => write!(f, "{:#09b}", self.0),
+        }
+    }
 }
 
 impl TryInto<Comp> for u8 {
     type Error = String;
     fn try_into(self) -> Result<Comp, Self::Error> {
-        match self {
-            0b0101010 => Ok(Comp::Zero),
-            0b0111111 => Ok(Comp::One),
-            0b0111010 => Ok(Comp::MinusOne),
-            0b0001100 => Ok(Comp::D),
-            0b0110000 => Ok(Comp::A),
-            0b0001101 => Ok(Comp::NotD),
-            0b0110001 => Ok(Comp::NotA),
-            0b0001111 => Ok(Comp::MinusD),
-            0b0110011 => Ok(Comp::MinusA),
-            0b0011111 => Ok(Comp::DPulsOne),
-            0b0110111 => Ok(Comp::APulsOne),
-            0b0110010 => Ok(Comp::DMinusOne), //is swapt to pass pong ???
-            0b0001110 => Ok(Comp::AMinusOne), //is swapt to pass pong ???
-            0b0000010 => Ok(Comp::DPulsA),
-            0b0010011 => Ok(Comp::DMinusA),
-            0b0000111 => Ok(Comp::AMinusD),
-            0b0000000 => Ok(Comp::DAndA),
-            0b0010101 => Ok(Comp::DOrA),
-            0b1110000 => Ok(Comp::M),
-            0b1110001 => Ok(Comp::NotM),
-            0b1110111 => Ok(Comp::MPlusOne),
-            0b1110010 => Ok(Comp::MMinusOne),
-            0b1000010 => Ok(Comp::DPulsM),
-            0b1010011 => Ok(Comp::DMinusM),
-            0b1000111 => Ok(Comp::MMinusD),
-            0b1000000 => Ok(Comp::DAndM),
-            0b1010101 => Ok(Comp::DOrM),
-            _ => Err(format!("Comp {:#b} is supported", self)),
+        if self <= 0b1111111 {
+            Ok(Comp(self))
+        } else {
+            Err(format!("Comp {:#b} is not 7 bits wide", self))
         }
     }
 }
@@ -87,6 +226,16 @@ pub enum Dest {
     AMD = 0b111,
 }
 
+/// empty for [`Dest::Null`]
+impl fmt::Display for Dest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Dest::Null => Ok(()),
+            dest => write!(f, "{:?}", dest),
+        }
+    }
+}
+
 impl TryInto<Dest> for u8 {
     type Error = String;
     fn try_into(self) -> Result<Dest, Self::Error> {
@@ -117,6 +266,16 @@ pub enum Jump {
     JMP = 0b111,
 }
 
+/// empty for [`Jump::Null`]
+impl fmt::Display for Jump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Jump::Null => Ok(()),
+            jump => write!(f, "{:?}", jump),
+        }
+    }
+}
+
 impl TryInto<Jump> for u8 {
     type Error = String;
     fn try_into(self) -> Result<Jump, Self::Error> {
@@ -139,3 +298,22 @@ pub enum CPUInstruction {
     AInstruc(i16),
     CInstruc(Comp, Dest, Jump),
 }
+
+/// hack assembly, a-instructions are written with the 15 bits [`asm2ml`] keeps
+impl fmt::Display for CPUInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CPUInstruction::AInstruc(val) => write!(f, "@{}", *val as u16 & 0x7fff),
+            CPUInstruction::CInstruc(comp, dest, jump) => {
+                if *dest != Dest::Null {
+                    write!(f, "{}=", dest)?;
+                }
+                write!(f, "{}", comp)?;
+                if *jump != Jump::Null {
+                    write!(f, ";{}", jump)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use super::parser::assemble_unresolved;
+use super::{asm2ml, ml2asm, AsmProgram, CPUInstruction, MachineProfile, SourcePos};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// first line of [`Object::to_text`]
+const OBJECT_HEADER: &str = "hack object";
+
+/// a separately assembled file. labels containing a `$` are local to it, all others are exported.
+/// names it loads that are neither its own labels nor predefined are left to the linker, they
+/// become an exported label of another object or else a variable of this one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub name: String,
+    /// a-instructions that load a label hold its address relative to the start of the object,
+    /// unresolved ones hold 0
+    pub instructions: Vec<CPUInstruction>,
+    pub exports: HashMap<String, usize>,
+    pub locals: HashMap<String, usize>,
+    /// addresses of the a-instructions that load one of the object's labels
+    pub relocations: Vec<usize>,
+    /// address and name of every unresolved a-instruction, in ascending order
+    pub references: Vec<(usize, String)>,
+    /// empty or the source position of every instruction
+    pub source_map: Vec<SourcePos>,
+}
+
+impl Object {
+    /// a line based text format to ship precompiled objects, see [`Object::from_text`]
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\nname {}\n", OBJECT_HEADER, self.name);
+        for (kind, labels) in [("export", &self.exports), ("local", &self.locals)] {
+            let mut labels: Vec<(&String, &usize)> = labels.iter().collect();
+            labels.sort_by_key(|&(label, addr)| (addr, label));
+            for (label, addr) in labels {
+                writeln!(text, "{} {} {}", kind, label, addr).unwrap();
+            }
+        }
+        for addr in &self.relocations {
+            writeln!(text, "reloc {}", addr).unwrap();
+        }
+        for (addr, name) in &self.references {
+            writeln!(text, "ref {} {}", addr, name).unwrap();
+        }
+        text.push_str("code\n");
+        let ml = asm2ml(self.instructions.clone());
+        for (addr, word) in ml.iter().enumerate() {
+            match self.source_map.get(addr) {
+                Some(pos) => writeln!(text, "{:016b} {}:{}", word, pos.line, pos.column).unwrap(),
+                None => writeln!(text, "{:016b}", word).unwrap(),
+            }
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, line)| line.trim()) != Some(OBJECT_HEADER) {
+            return Err(format!("an object has to start with `{}`", OBJECT_HEADER));
+        }
+
+        let mut object = Object {
+            name: String::new(),
+            instructions: Vec::new(),
+            exports: HashMap::new(),
+            locals: HashMap::new(),
+            relocations: Vec::new(),
+            references: Vec::new(),
+            source_map: Vec::new(),
+        };
+        let mut ml = Vec::new();
+        let mut in_code = false;
+        for (i, line) in lines {
+            let err = || format!("unexpected `{}` in line {}", line, i + 1);
+            let addr = |text: &str| text.parse::<usize>().map_err(|_| err());
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if in_code {
+                let word = parts
+                    .first()
+                    .and_then(|word| u16::from_str_radix(word, 2).ok())
+                    .ok_or_else(err)?;
+                ml.push(word);
+                if let Some(pos) = parts.get(1) {
+                    let (line, column) = pos.split_once(':').ok_or_else(err)?;
+                    object.source_map.push(SourcePos {
+                        line: addr(line)?,
+                        column: addr(column)?,
+                    });
+                }
+                continue;
+            }
+            if let Some(name) = line.strip_prefix("name ") {
+                object.name = name.to_string();
+                continue;
+            }
+            match parts[..] {
+                ["export", label, val] => {
+                    object.exports.insert(label.to_string(), addr(val)?);
+                }
+                ["local", label, val] => {
+                    object.locals.insert(label.to_string(), addr(val)?);
+                }
+                ["reloc", val] => object.relocations.push(addr(val)?),
+                ["ref", val, name] => object.references.push((addr(val)?, name.to_string())),
+                ["code"] => in_code = true,
+                _ => return Err(err()),
+            }
+        }
+
+        object.instructions = ml2asm(ml)?;
+        let len = object.instructions.len();
+        let outside = object
+            .relocations
+            .iter()
+            .chain(object.references.iter().map(|(addr, _)| addr))
+            .find(|&&addr| addr >= len);
+        if let Some(addr) = outside {
+            return Err(format!(
+                "the relocation at {} is outside of the {} instructions",
+                addr, len
+            ));
+        }
+        Ok(object)
+    }
+}
+
+/// assembles `code` into an object called `name`, fails with the first error prefixed by the name
+pub fn assemble_object(name: &str, code: &str) -> Result<Object, String> {
+    assemble_object_for(name, code, &MachineProfile::default())
+}
+
+/// like [`assemble_object`] with the predefined symbols and rom size of `machine`
+pub fn assemble_object_for(
+    name: &str,
+    code: &str,
+    machine: &MachineProfile,
+) -> Result<Object, String> {
+    let (assembly, references) = assemble_unresolved(code, machine);
+    let program = match assembly.program {
+        Some(program) => program,
+        None => {
+            let error = assembly.errors().next().unwrap();
+            return Err(format!("{}:{}", name, error));
+        }
+    };
+
+    let mut instructions = program.instructions;
+    for (addr, _) in &references {
+        instructions[*addr] = CPUInstruction::AInstruc(0);
+    }
+    let (locals, exports) = program
+        .labels
+        .into_iter()
+        .partition(|(label, _)| label.contains('$'));
+    Ok(Object {
+        name: name.to_string(),
+        instructions,
+        exports,
+        locals,
+        relocations: program.relocations,
+        references,
+        source_map: program.source_map,
+    })
+}
+
+/// where an object ended up in a linked program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub name: String,
+    /// rom address of the first instruction
+    pub start: usize,
+    pub len: usize,
+    /// all labels of the object with their rom address
+    pub labels: HashMap<String, usize>,
+    /// the ram address of every variable of the object
+    pub variables: HashMap<String, usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Linked {
+    /// its labels are the exported ones and local ones that only one object defines, its variables
+    /// the ones only one object uses. [`Linked::sections`] has all of them
+    pub program: AsmProgram,
+    pub sections: Vec<Section>,
+}
+
+impl Linked {
+    /// the section that contains the rom address `addr`
+    pub fn section_of(&self, addr: usize) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|section| (section.start..section.start + section.len).contains(&addr))
+    }
+}
+
+/// places the objects in rom one after another, so the first one starts at 0 and is the entry point.
+/// every object gets its own variables, allocated from 16 upwards in the order of the objects
+pub fn link(objects: &[Object]) -> Result<Linked, String> {
+    link_for(objects, &MachineProfile::default())
+}
+
+/// like [`link`] for the rom size of `machine`, variables start at its statics and must stay below
+/// its screen
+pub fn link_for(objects: &[Object], machine: &MachineProfile) -> Result<Linked, String> {
+    let mut exports: HashMap<&str, (usize, &str)> = HashMap::new();
+    let mut start = 0;
+    let mut starts = Vec::new();
+    for object in objects {
+        for (label, addr) in &object.exports {
+            if let Some((_, other)) =
+                exports.insert(label.as_str(), (start + addr, object.name.as_str()))
+            {
+                return Err(format!(
+                    "the label {} is exported by {} and {}",
+                    label, other, object.name
+                ));
+            }
+        }
+        starts.push(start);
+        start += object.instructions.len();
+    }
+    if start > machine.rom_size {
+        return Err(format!(
+            "the linked program has {} instructions but the rom only holds {}",
+            start, machine.rom_size
+        ));
+    }
+
+    let mut instructions = Vec::with_capacity(start);
+    let mut relocations = Vec::new();
+    let mut source_map = Vec::new();
+    let mut sections = Vec::new();
+    let mut var_count = machine.statics;
+    for (object, &start) in objects.iter().zip(&starts) {
+        let mut code = object.instructions.clone();
+        for &addr in &object.relocations {
+            if let CPUInstruction::AInstruc(val) = code[addr] {
+                code[addr] = load(start + val as usize, "a label", &object.name)?;
+            }
+            relocations.push(start + addr);
+        }
+
+        let mut variables = HashMap::new();
+        for (addr, name) in &object.references {
+            let val = match exports.get(name.as_str()) {
+                Some(&(label, _)) => {
+                    relocations.push(start + addr);
+                    label
+                }
+                None => *variables.entry(name.clone()).or_insert_with(|| {
+                    var_count += 1;
+                    var_count - 1
+                }),
+            };
+            code[*addr] = load(val, name, &object.name)?;
+        }
+        if var_count > machine.screen {
+            return Err(format!(
+                "the variables of {} reach into the screen at {}",
+                object.name, machine.screen
+            ));
+        }
+
+        instructions.extend(code);
+        if object.source_map.len() == object.instructions.len() {
+            source_map.extend(&object.source_map);
+        }
+        let labels = object
+            .exports
+            .iter()
+            .chain(&object.locals)
+            .map(|(label, addr)| (label.clone(), start + addr))
+            .collect();
+        sections.push(Section {
+            name: object.name.clone(),
+            start,
+            len: object.instructions.len(),
+            labels,
+            variables,
+        });
+    }
+    relocations.sort_unstable();
+    if source_map.len() != instructions.len() {
+        source_map.clear();
+    }
+
+    let program = AsmProgram {
+        instructions,
+        labels: unique(sections.iter().map(|section| &section.labels)),
+        variables: unique(sections.iter().map(|section| &section.variables)),
+        relocations,
+        source_map,
+    };
+    Ok(Linked { program, sections })
+}
+
+/// an a-instruction that loads the address of `name` in `object`
+fn load(val: usize, name: &str, object: &str) -> Result<CPUInstruction, String> {
+    if val > 0x7fff {
+        return Err(format!(
+            "{} of {} is at {} but a-instructions only reach 32767",
+            name, object, val
+        ));
+    }
+    Ok(CPUInstruction::AInstruc(val as i16))
+}
+
+/// the names that are in only one of the tables
+fn unique<'a>(tables: impl Iterator<Item = &'a HashMap<String, usize>>) -> HashMap<String, usize> {
+    let mut merged: HashMap<String, Option<usize>> = HashMap::new();
+    for table in tables {
+        for (name, &addr) in table {
+            merged
+                .entry(name.clone())
+                .and_modify(|val| *val = None)
+                .or_insert(Some(addr));
+        }
+    }
+    merged
+        .into_iter()
+        .filter_map(|(name, addr)| Some((name, addr?)))
+        .collect()
+}
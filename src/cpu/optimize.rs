@@ -0,0 +1,448 @@
+use super::{AsmProgram, CPUInstruction, Comp, Dest, HackCpu, Jump, RunOutcome};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::ops::Range;
+
+/// the rewrites of [`optimize`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Rewrite {
+    /// `@X` while A already holds X
+    RedundantLoad,
+    /// instructions after an unconditional jump that no label points to
+    DeadCode,
+    /// `D=D`, `A=A` and c-instructions without dest and jump. instructions that read M are kept,
+    /// a device mapped at A can count the read
+    NoOp,
+    /// a jump to `@Y 0;JMP` jumps to Y directly
+    JumpChain,
+    /// `@SP A=M M=D @SP M=M+1` becomes `@SP AM=M+1 A=A-1 M=D` and `@SP A=M D=M @SP M=M-1`
+    /// becomes `@SP M=M-1 A=M+1 D=M` if A is not read afterwards. this assumes SP never points to itself
+    StackIdiom,
+    /// `A=...` directly before an a-instruction, unless it reads M
+    DeadStore,
+}
+
+/// the result of [`optimize`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Optimized {
+    pub program: AsmProgram,
+    /// the new rom address of every old one and of the end of the old rom,
+    /// removed instructions map to the instruction that took their place
+    pub addr_map: Vec<usize>,
+    /// how often each rewrite was applied
+    pub rewrites: BTreeMap<Rewrite, usize>,
+}
+
+impl Optimized {
+    /// rom words saved
+    pub fn saved(&self) -> usize {
+        self.addr_map.len() - 1 - self.program.instructions.len()
+    }
+
+    pub fn report(&self) -> String {
+        let before = self.addr_map.len() - 1;
+        let mut text = format!(
+            "{} -> {} instructions, {} rom words saved\n",
+            before,
+            self.program.instructions.len(),
+            self.saved()
+        );
+        for (rewrite, count) in &self.rewrites {
+            writeln!(text, "{:>8} {:?}", count, rewrite).unwrap();
+        }
+        text
+    }
+
+    /// runs `original` and the optimized program on fresh cpus prepared by `setup` until they halt
+    /// and compares the d register and the ram. the a register is not compared, the optimizer only
+    /// keeps it where it is read. rom addresses left in D or the ram differ if code moved
+    pub fn verify<F>(
+        &self,
+        original: &AsmProgram,
+        max_cycles: usize,
+        setup: F,
+    ) -> Result<(), String>
+    where
+        F: Fn(&mut HackCpu),
+    {
+        let run = |program: &AsmProgram, name: &str| {
+            let mut cpu = HackCpu::from_program(program);
+            setup(&mut cpu);
+            match cpu.run(max_cycles) {
+                RunOutcome::Halted { .. } => Ok(cpu),
+                outcome => Err(format!("the {} program did not halt: {:?}", name, outcome)),
+            }
+        };
+        let before = run(original, "original")?;
+        let after = run(&self.program, "optimized")?;
+
+        if before.d_reg() != after.d_reg() {
+            return Err(format!(
+                "D is {} but should be {}",
+                after.d_reg(),
+                before.d_reg()
+            ));
+        }
+        let ram = |cpu: &HackCpu| cpu.ram_range(0..0x8000);
+        let (expected, got) = (ram(&before), ram(&after));
+        match expected.iter().zip(&got).position(|(e, g)| e != g) {
+            Some(addr) => Err(format!(
+                "RAM[{}] is {} but should be {}",
+                addr, got[addr], expected[addr]
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// applies peephole rewrites until none matches anymore. instructions move, so a-instructions that
+/// load a label (see [`AsmProgram::relocations`]) or are directly followed by a jump are relocated.
+/// a program that computes rom addresses or loads them as numbers for later is not optimized correctly
+pub fn optimize(program: &AsmProgram) -> Optimized {
+    let mut code = Code::new(program);
+    let mut rewrites = BTreeMap::new();
+    // stack idioms go first, removing redundant loads breaks them up
+    let passes: [(Rewrite, Pass); 6] = [
+        (Rewrite::StackIdiom, stack_idioms),
+        (Rewrite::JumpChain, jump_chains),
+        (Rewrite::DeadCode, dead_code),
+        (Rewrite::NoOp, no_ops),
+        (Rewrite::RedundantLoad, redundant_loads),
+        (Rewrite::DeadStore, dead_stores),
+    ];
+    loop {
+        let mut changed = false;
+        for (rewrite, pass) in passes {
+            let edits = pass(&code);
+            if !edits.is_empty() {
+                *rewrites.entry(rewrite).or_insert(0) += edits.len();
+                code.apply(edits);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let addr_map = code.map;
+    let source_map = code
+        .origin
+        .iter()
+        .filter_map(|&old| program.source_pos(old))
+        .collect();
+    let optimized = AsmProgram {
+        labels: program
+            .labels
+            .iter()
+            .map(|(label, &addr)| (label.clone(), addr_map[addr]))
+            .collect(),
+        variables: program.variables.clone(),
+        relocations: (0..code.ins.len()).filter(|&i| code.reloc[i]).collect(),
+        source_map,
+        instructions: code.ins,
+    };
+    Optimized {
+        program: optimized,
+        addr_map,
+        rewrites,
+    }
+}
+
+type Pass = fn(&Code) -> Vec<Edit>;
+
+/// replaces the instructions in `range` by `with`, the bool marks relocated a-instructions
+struct Edit {
+    range: Range<usize>,
+    with: Vec<(CPUInstruction, bool)>,
+}
+
+impl Edit {
+    fn remove(addr: usize) -> Self {
+        Edit {
+            range: addr..addr + 1,
+            with: Vec::new(),
+        }
+    }
+}
+
+struct Code {
+    ins: Vec<CPUInstruction>,
+    reloc: Vec<bool>,
+    /// the original address of every instruction, replacements come from the start of what they replace
+    origin: Vec<usize>,
+    /// the current address of every original one
+    map: Vec<usize>,
+}
+
+impl Code {
+    fn new(program: &AsmProgram) -> Self {
+        let ins = &program.instructions;
+        let mut reloc = vec![false; ins.len()];
+        for &addr in &program.relocations {
+            reloc[addr] = true;
+        }
+        // A holds a jump target, even if it is written as a number
+        for addr in 0..ins.len().saturating_sub(1) {
+            if let (CPUInstruction::AInstruc(val), CPUInstruction::CInstruc(_, _, jump)) =
+                (&ins[addr], &ins[addr + 1])
+            {
+                if *jump != Jump::Null && (*val as usize) <= ins.len() {
+                    reloc[addr] = true;
+                }
+            }
+        }
+        Code {
+            ins: program.instructions.clone(),
+            reloc,
+            origin: (0..program.instructions.len()).collect(),
+            map: (0..=program.instructions.len()).collect(),
+        }
+    }
+
+    /// addresses control can reach other than by falling through
+    fn targets(&self) -> HashSet<usize> {
+        let mut targets: HashSet<usize> = self
+            .ins
+            .iter()
+            .zip(&self.reloc)
+            .filter_map(|(ins, &reloc)| match ins {
+                CPUInstruction::AInstruc(val) if reloc => Some(*val as usize),
+                _ => None,
+            })
+            .collect();
+        targets.insert(0);
+        targets
+    }
+
+    /// `edits` must be sorted and must not overlap
+    fn apply(&mut self, edits: Vec<Edit>) {
+        let mut ins = Vec::with_capacity(self.ins.len());
+        let mut reloc = Vec::with_capacity(self.ins.len());
+        let mut origin = Vec::with_capacity(self.ins.len());
+        let mut step = Vec::with_capacity(self.ins.len() + 1);
+        let mut edits = edits.into_iter().peekable();
+        let mut addr = 0;
+        while addr < self.ins.len() {
+            match edits.next_if(|edit| edit.range.start == addr) {
+                Some(edit) => {
+                    step.extend(edit.range.clone().map(|_| ins.len()));
+                    for (i, r) in edit.with {
+                        ins.push(i);
+                        reloc.push(r);
+                        origin.push(self.origin[addr]);
+                    }
+                    addr = edit.range.end;
+                }
+                None => {
+                    step.push(ins.len());
+                    ins.push(self.ins[addr].clone());
+                    reloc.push(self.reloc[addr]);
+                    origin.push(self.origin[addr]);
+                    addr += 1;
+                }
+            }
+        }
+        step.push(ins.len());
+
+        for (i, r) in ins.iter_mut().zip(&reloc) {
+            if let CPUInstruction::AInstruc(val) = i {
+                if *r && (*val as usize) < step.len() {
+                    *val = step[*val as usize] as i16;
+                }
+            }
+        }
+        for addr in &mut self.map {
+            *addr = step[*addr];
+        }
+        self.ins = ins;
+        self.reloc = reloc;
+        self.origin = origin;
+    }
+
+    /// the relocated a-instruction at `addr`
+    fn label_ref(&self, addr: usize) -> Option<usize> {
+        match self.ins.get(addr) {
+            Some(CPUInstruction::AInstruc(val)) if self.reloc[addr] => Some(*val as usize),
+            _ => None,
+        }
+    }
+
+    /// true if the instruction at `addr` overwrites A without reading it
+    fn writes_a(&self, addr: usize) -> bool {
+        matches!(self.ins.get(addr), Some(CPUInstruction::AInstruc(_)))
+    }
+}
+
+fn is_unconditional(ins: &CPUInstruction) -> bool {
+    matches!(ins, CPUInstruction::CInstruc(_, _, Jump::JMP))
+}
+
+fn writes_to_a(dest: &Dest) -> bool {
+    matches!(dest, Dest::A | Dest::AM | Dest::AD | Dest::AMD)
+}
+
+fn jump_chains(code: &Code) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    for addr in 0..code.ins.len() {
+        let target = match (code.label_ref(addr), code.ins.get(addr + 1)) {
+            // the jump must neither read nor store A, which will hold the new target
+            (Some(target), Some(CPUInstruction::CInstruc(comp, Dest::Null, jump)))
+                if *jump != Jump::Null && comp.zy() =>
+            {
+                // falling through a conditional jump leaves the new target in A
+                if *jump != Jump::JMP && !code.writes_a(addr + 2) {
+                    continue;
+                }
+                target
+            }
+            _ => continue,
+        };
+
+        let mut end = target;
+        let mut seen = HashSet::new();
+        while let (Some(next), Some(jump)) = (code.label_ref(end), code.ins.get(end + 1)) {
+            if *jump != CPUInstruction::CInstruc(Comp::Zero, Dest::Null, Jump::JMP)
+                || !seen.insert(end)
+            {
+                break;
+            }
+            end = next;
+        }
+        if end != target {
+            edits.push(Edit {
+                range: addr..addr + 1,
+                with: vec![(CPUInstruction::AInstruc(end as i16), true)],
+            });
+        }
+    }
+    edits
+}
+
+fn dead_code(code: &Code) -> Vec<Edit> {
+    let targets = code.targets();
+    let mut edits = Vec::new();
+    let mut reachable = true;
+    for (addr, ins) in code.ins.iter().enumerate() {
+        if targets.contains(&addr) {
+            reachable = true;
+        }
+        if !reachable {
+            edits.push(Edit::remove(addr));
+        }
+        if is_unconditional(ins) {
+            reachable = false;
+        }
+    }
+    edits
+}
+
+fn no_ops(code: &Code) -> Vec<Edit> {
+    code.ins
+        .iter()
+        .enumerate()
+        .filter(|(_, ins)| match ins {
+            CPUInstruction::CInstruc(comp, dest, Jump::Null) if !comp.a() => {
+                matches!(
+                    (*comp, dest),
+                    (_, Dest::Null) | (Comp::D, Dest::D) | (Comp::A, Dest::A)
+                )
+            }
+            _ => false,
+        })
+        .map(|(addr, _)| Edit::remove(addr))
+        .collect()
+}
+
+fn redundant_loads(code: &Code) -> Vec<Edit> {
+    let targets = code.targets();
+    let mut edits = Vec::new();
+    let mut a = None;
+    for (addr, ins) in code.ins.iter().enumerate() {
+        if targets.contains(&addr) {
+            a = None;
+        }
+        match ins {
+            CPUInstruction::AInstruc(val) => {
+                let loaded = Some((*val, code.reloc[addr]));
+                if a == loaded {
+                    edits.push(Edit::remove(addr));
+                }
+                a = loaded;
+            }
+            CPUInstruction::CInstruc(_, dest, _) if writes_to_a(dest) => a = None,
+            CPUInstruction::CInstruc(..) => (),
+        }
+    }
+    edits
+}
+
+fn dead_stores(code: &Code) -> Vec<Edit> {
+    (0..code.ins.len())
+        .filter(|&addr| {
+            matches!(
+                code.ins[addr],
+                CPUInstruction::CInstruc(comp, Dest::A, Jump::Null) if !comp.a()
+            ) && code.writes_a(addr + 1)
+        })
+        .map(Edit::remove)
+        .collect()
+}
+
+fn stack_idioms(code: &Code) -> Vec<Edit> {
+    use CPUInstruction::CInstruc;
+    let c = |comp, dest| CInstruc(comp, dest, Jump::Null);
+    let idioms = [
+        (
+            [c(Comp::M, Dest::A), c(Comp::D, Dest::M)],
+            c(Comp::MPlusOne, Dest::M),
+            [
+                c(Comp::MPlusOne, Dest::AM),
+                c(Comp::AMinusOne, Dest::A),
+                c(Comp::D, Dest::M),
+            ],
+        ),
+        (
+            [c(Comp::M, Dest::A), c(Comp::M, Dest::D)],
+            c(Comp::MMinusOne, Dest::M),
+            [
+                c(Comp::MMinusOne, Dest::M),
+                c(Comp::MPlusOne, Dest::A),
+                c(Comp::M, Dest::D),
+            ],
+        ),
+    ];
+
+    let targets = code.targets();
+    let mut edits = Vec::new();
+    let mut addr = 0;
+    let sp = CPUInstruction::AInstruc(crate::SP as i16);
+    // `@SP first @SP second`, A must be dead after it
+    while addr + 5 < code.ins.len() {
+        let window = &code.ins[addr..addr + 5];
+        let matched = idioms.iter().find(|(first, second, _)| {
+            window[0] == sp
+                && window[3] == sp
+                && !code.reloc[addr]
+                && !code.reloc[addr + 3]
+                && window[1..3] == first[..]
+                && window[4] == *second
+        });
+        match matched {
+            Some((_, _, with))
+                if (addr + 1..addr + 5).all(|a| !targets.contains(&a))
+                    && code.writes_a(addr + 5) =>
+            {
+                let mut replacement = vec![(sp.clone(), false)];
+                replacement.extend(with.iter().map(|ins| (ins.clone(), false)));
+                edits.push(Edit {
+                    range: addr..addr + 5,
+                    with: replacement,
+                });
+                addr += 5;
+            }
+            _ => addr += 1,
+        }
+    }
+    edits
+}
@@ -1,7 +1,11 @@
-use super::{CPUInstruction, Comp, Dest};
+use super::{
+    AsmProgram, Assembly, CPUInstruction, Comp, Dest, Diagnostic, MachineProfile, Severity,
+    SourcePos,
+};
 use logos::{Lexer, Logos};
 use std::collections::HashMap;
-use tokenizer::{Error, Tokenizer, TypeEq};
+use std::ops::Range;
+use tokenizer::{Error, TypeEq};
 
 pub fn asm2ml(asm: Vec<CPUInstruction>) -> Vec<u16> {
     let mut ml = Vec::new();
@@ -9,7 +13,7 @@ pub fn asm2ml(asm: Vec<CPUInstruction>) -> Vec<u16> {
         match instrc {
             CPUInstruction::AInstruc(val) => ml.push((val & 0b0111111111111111) as u16),
             CPUInstruction::CInstruc(comp, dest, jump) => {
-                ml.push(0b111 << 13 | (comp as u16) << 6 | (dest as u16) << 3 | jump as u16)
+                ml.push(0b111 << 13 | (comp.bits() as u16) << 6 | (dest as u16) << 3 | jump as u16)
             }
         }
     }
@@ -74,11 +78,56 @@ pub fn str2ml(hack: &str) -> Result<Vec<u16>, String> {
 }
 
 pub fn parse(code: &str) -> Result<Vec<CPUInstruction>, Error> {
-    let mut tokenizer = Tokenizer::new(Token::lexer(code), vec![Token::Ignore((0, None))]);
-    let mut asm = Vec::new();
+    Ok(parse_program(code)?.instructions)
+}
+
+/// like [`parse`] but also returns the rom address of every label defined in the code
+pub fn parse_with_labels(
+    code: &str,
+) -> Result<(Vec<CPUInstruction>, HashMap<String, usize>), Error> {
+    let program = parse_program(code)?;
+    Ok((program.instructions, program.labels))
+}
+
+/// assembles `code` and keeps the symbol tables and the position of every instruction in the source,
+/// fails with the first error. use [`assemble`] to get all errors and warnings
+pub fn parse_program(code: &str) -> Result<AsmProgram, Error> {
+    parse_program_for(code, &MachineProfile::default())
+}
+
+/// like [`parse_program`] for another machine, see [`assemble_for`]
+pub fn parse_program_for(code: &str, machine: &MachineProfile) -> Result<AsmProgram, Error> {
+    let assembly = assemble_for(code, machine);
+    match assembly.program {
+        Some(program) => Ok(program),
+        None => {
+            let error = assembly.errors().next().unwrap();
+            Err(Error::new(None, None, error.to_string()))
+        }
+    }
+}
 
+/// assembles `code` and collects every error and warning instead of stopping at the first error
+pub fn assemble(code: &str) -> Assembly {
+    assemble_for(code, &MachineProfile::default())
+}
+
+/// like [`assemble`] but `SCREEN`, `KBD`, the first variable and the rom size are the ones of `machine`
+pub fn assemble_for(code: &str, machine: &MachineProfile) -> Assembly {
+    Assembler::new(code, machine).assemble().0
+}
+
+/// like [`assemble`] but also returns the rom address and name of every a-instruction that loads
+/// a variable, these are the references an object leaves to the linker
+pub(super) fn assemble_unresolved(
+    code: &str,
+    machine: &MachineProfile,
+) -> (Assembly, Vec<(usize, String)>) {
+    Assembler::new(code, machine).assemble()
+}
+
+fn predefined(machine: &MachineProfile) -> HashMap<String, usize> {
     let mut labals = HashMap::new();
-    let mut names = Vec::new();
 
     labals.insert(String::from("R0"), 0);
     labals.insert(String::from("R1"), 1);
@@ -96,162 +145,366 @@ pub fn parse(code: &str) -> Result<Vec<CPUInstruction>, Error> {
     labals.insert(String::from("R13"), 13);
     labals.insert(String::from("R14"), 14);
     labals.insert(String::from("R15"), 15);
-    labals.insert(String::from("R16"), 16);
 
     labals.insert(String::from("SP"), crate::SP);
     labals.insert(String::from("LCL"), crate::LCL);
     labals.insert(String::from("ARG"), crate::ARG);
     labals.insert(String::from("THIS"), crate::THIS);
     labals.insert(String::from("THAT"), crate::THAT);
-    labals.insert(String::from("SCREEN"), crate::SCREEN);
-    labals.insert(String::from("KBD"), crate::KBD);
-
-    while let Some(token) = tokenizer.next() {
-        match token {
-            Token::Labal(labal) => {
-                if let Some(val) = labals.insert(labal.clone(), asm.len()) {
-                    return Err(tokenizer.error(&format!(
-                        "the label {} has already been set to {}",
-                        labal, val
-                    )));
-                }
-            }
-            Token::Name(name) => {
-                names.push((name, asm.len()));
-                asm.push(CPUInstruction::AInstruc(0));
-            }
-            Token::Number(val) => asm.push(CPUInstruction::AInstruc(val as i16)),
-            _ => asm.push(c_instruc(&mut tokenizer)?),
+    labals.insert(String::from("SCREEN"), machine.screen);
+    labals.insert(String::from("KBD"), machine.keyboard);
+
+    labals
+}
+
+struct Assembler<'a> {
+    code: &'a str,
+    machine: &'a MachineProfile,
+    tokens: Vec<(Token, Range<usize>)>,
+    next: usize,
+
+    asm: Vec<CPUInstruction>,
+    /// source offset of every instruction
+    offsets: Vec<usize>,
+    /// `@name` references, resolved after all labels are known
+    names: Vec<(String, usize, Range<usize>)>,
+    defined: HashMap<String, (usize, Range<usize>)>,
+
+    diagnostics: Vec<(Severity, Range<usize>, String)>,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(code: &'a str, machine: &'a MachineProfile) -> Self {
+        Self {
+            code,
+            machine,
+            tokens: Token::lexer(code)
+                .spanned()
+                .filter(|(token, _)| !matches!(token, Token::Ignore(_)))
+                .collect(),
+            next: 0,
+            asm: Vec::new(),
+            offsets: Vec::new(),
+            names: Vec::new(),
+            defined: HashMap::new(),
+            diagnostics: Vec::new(),
         }
     }
 
-    // resolve labels
-    let mut var_count = 16;
-    for (name, line) in names {
-        let val = if let Some(&num) = labals.get(&name) {
-            num
-        } else {
-            labals.insert(name, var_count);
-            var_count += 1;
-            var_count - 1
-        };
+    fn error(&mut self, span: Range<usize>, msg: String) {
+        self.diagnostics.push((Severity::Error, span, msg));
+    }
 
-        asm[line] = CPUInstruction::AInstruc(val as i16);
+    fn warning(&mut self, span: Range<usize>, msg: String) {
+        self.diagnostics.push((Severity::Warning, span, msg));
     }
 
-    Ok(asm)
-}
+    fn next(&mut self) -> Option<(Token, Range<usize>)> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
 
-fn get_token(token: Option<Token>, tokenizer: &Tokenizer<Token>) -> Result<Token, Error> {
-    if let Some(token) = token {
-        Ok(token)
-    } else {
-        Err(tokenizer.error("unexpected end of file"))
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
     }
-}
 
-fn c_instruc(tokenizer: &mut Tokenizer<Token>) -> Result<CPUInstruction, Error> {
-    let token = get_token(tokenizer.current(), tokenizer)?;
+    fn push(&mut self, instruction: CPUInstruction, offset: usize) {
+        self.asm.push(instruction);
+        self.offsets.push(offset);
+    }
 
-    let dest;
-    let comp;
-    let jump;
+    /// skips the rest of the line of the last token, so one mistake is reported only once
+    fn skip_line(&mut self) {
+        while let (Some((_, prev)), Some((_, span))) =
+            (self.tokens.get(self.next - 1), self.tokens.get(self.next))
+        {
+            if self.code[prev.end..span.start].contains('\n') {
+                break;
+            }
+            self.next += 1;
+        }
+    }
 
-    let t = get_token(tokenizer.peek(), tokenizer)?;
-    if t == Token::Eq {
-        dest = get_dest(token.clone());
-        tokenizer.expect_next(Token::Eq)?;
-        comp = match get_comp(get_token(tokenizer.next(), tokenizer)?) {
-            Ok(val) => val,
-            Err(msg) => return Err(tokenizer.error(&msg)),
+    fn assemble(mut self) -> (Assembly, Vec<(usize, String)>) {
+        let predefined = predefined(self.machine);
+
+        while let Some((token, span)) = self.next() {
+            match token {
+                Token::Labal(labal) => {
+                    if let Some((val, _)) = self.defined.get(&labal) {
+                        let msg = format!("the label {} has already been set to {}", labal, val);
+                        self.error(span, msg);
+                        continue;
+                    }
+                    if let Some(val) = predefined.get(&labal) {
+                        let msg = format!(
+                            "the label {} shadows the predefined symbol {} = {}",
+                            labal, labal, val
+                        );
+                        self.warning(span.clone(), msg);
+                    }
+                    self.defined.insert(labal, (self.asm.len(), span));
+                }
+                Token::Name(name) => {
+                    self.names.push((name, self.asm.len(), span.clone()));
+                    self.push(CPUInstruction::AInstruc(0), span.start);
+                }
+                Token::Number(val) => {
+                    if val > i16::MAX as usize {
+                        let msg = format!(
+                            "the constant {} does not fit into 15 bits and is truncated to {}",
+                            val,
+                            val & 0x7fff
+                        );
+                        self.warning(span.clone(), msg);
+                    }
+                    self.push(CPUInstruction::AInstruc(val as i16), span.start);
+                }
+                Token::Unknown => {
+                    let span = self.word(span);
+                    let msg = format!("unexpected `{}`", &self.code[span.clone()]);
+                    self.error(span.clone(), msg);
+                    self.push(CPUInstruction::AInstruc(0), span.start);
+                    self.skip_line();
+                }
+                _ => {
+                    let instruction = match self.c_instruc(token, span.clone()) {
+                        Ok(instruction) => instruction,
+                        Err((span, msg)) => {
+                            self.error(span, msg);
+                            self.skip_line();
+                            CPUInstruction::AInstruc(0)
+                        }
+                    };
+                    self.push(instruction, span.start);
+                }
+            }
         }
-    } else {
-        dest = Dest::Null;
-        comp = match get_comp(token) {
-            Ok(val) => val,
-            Err(msg) => return Err(tokenizer.error(&msg)),
+
+        let rom_size = self.machine.rom_size;
+        if self.asm.len() > rom_size {
+            let offset = self.offsets[rom_size];
+            let msg = format!(
+                "the program has {} instructions but the rom only holds {}",
+                self.asm.len(),
+                rom_size
+            );
+            self.warning(offset..offset, msg);
         }
-    }
 
-    if let Some(token) = tokenizer.peek() {
-        if token == Token::Semic {
-            tokenizer.expect_next(Token::Semic)?;
-            if let Some(token) = tokenizer.next() {
-                jump = get_jump(token);
+        // resolve labels
+        let mut var_count = self.machine.statics;
+        let mut variables = HashMap::new();
+        let mut uses: HashMap<String, Vec<Range<usize>>> = HashMap::new();
+        let mut relocations = Vec::new();
+        let mut unresolved = Vec::new();
+        for (name, addr, span) in std::mem::take(&mut self.names) {
+            let val = if let Some(&(num, _)) = self.defined.get(&name) {
+                relocations.push(addr);
+                num
+            } else if let Some(&num) = predefined.get(&name) {
+                num
             } else {
-                return Err(tokenizer.error("expected jump after semicolon"));
+                unresolved.push((addr, name.clone()));
+                *variables.entry(name.clone()).or_insert_with(|| {
+                    var_count += 1;
+                    var_count - 1
+                })
+            };
+            if val > 0x7fff {
+                let msg = format!("{} is at {} but a-instructions only reach 32767", name, val);
+                self.error(span.clone(), msg);
+            }
+            uses.entry(name).or_default().push(span);
+
+            self.asm[addr] = CPUInstruction::AInstruc(val as i16);
+        }
+
+        for (labal, (_, span)) in self.defined.clone() {
+            if !uses.contains_key(&labal) {
+                self.warning(span, format!("the label {} is never used", labal));
+            }
+        }
+        for name in variables.keys() {
+            if let [span] = &uses[name][..] {
+                let msg = format!("the variable {} is only used once, is it a typo?", name);
+                self.warning(span.clone(), msg);
             }
+        }
+
+        self.diagnostics.sort_by_key(|(_, span, _)| span.start);
+        let offsets: Vec<usize> = self
+            .diagnostics
+            .iter()
+            .map(|(_, span, _)| span.start)
+            .collect();
+        let diagnostics: Vec<Diagnostic> = self
+            .diagnostics
+            .into_iter()
+            .zip(SourcePos::from_offsets(self.code, &offsets))
+            .map(|((severity, span, message), pos)| Diagnostic {
+                severity,
+                span,
+                pos,
+                message,
+            })
+            .collect();
+
+        let program = if diagnostics.iter().any(|d| d.is_error()) {
+            None
         } else {
-            jump = super::Jump::Null;
+            Some(AsmProgram {
+                source_map: SourcePos::from_offsets(self.code, &self.offsets),
+                instructions: self.asm,
+                labels: self
+                    .defined
+                    .into_iter()
+                    .map(|(labal, (addr, _))| (labal, addr))
+                    .collect(),
+                variables,
+                relocations,
+            })
+        };
+
+        let assembly = Assembly {
+            program,
+            diagnostics,
+        };
+        (assembly, unresolved)
+    }
+
+    /// `dest=comp;jump` where `dest=` and `;jump` are optional, `token` is the first token
+    fn c_instruc(
+        &mut self,
+        token: Token,
+        span: Range<usize>,
+    ) -> Result<CPUInstruction, (Range<usize>, String)> {
+        let (dest, (comp_token, comp_span)) = if self.peek() == Some(&Token::Eq) {
+            let dest = match get_dest(&token) {
+                Some(dest) => dest,
+                None => return Err(self.unexpected(span, "a destination")),
+            };
+            let (_, eq_span) = self.next().unwrap();
+            (dest, self.expect_some(eq_span, "a computation")?)
+        } else {
+            (Dest::Null, (token, span))
+        };
+
+        let comp = match get_comp(&comp_token) {
+            Some(comp) => comp,
+            None => return Err(self.unexpected(comp_span, "a computation")),
+        };
+
+        let jump = if self.peek() == Some(&Token::Semic) {
+            let (_, semic_span) = self.next().unwrap();
+            let (jump_token, jump_span) = self.expect_some(semic_span, "a jump")?;
+            match get_jump(&jump_token) {
+                Some(jump) => jump,
+                None => return Err(self.unexpected(jump_span, "a jump")),
+            }
+        } else {
+            super::Jump::Null
+        };
+
+        Ok(CPUInstruction::CInstruc(comp, dest, jump))
+    }
+
+    fn expect_some(
+        &mut self,
+        prev: Range<usize>,
+        expected: &str,
+    ) -> Result<(Token, Range<usize>), (Range<usize>, String)> {
+        match self.next() {
+            Some(token) => Ok(token),
+            None => Err((
+                prev.end..prev.end,
+                format!("expected {} but got the end of the file", expected),
+            )),
         }
-    } else {
-        jump = super::Jump::Null;
     }
 
-    Ok(CPUInstruction::CInstruc(comp, dest, jump))
+    /// extends `span` to the end of the word, the lexer reports unknown input one char at a time
+    fn word(&self, span: Range<usize>) -> Range<usize> {
+        let len = self.code[span.end..]
+            .find(|c: char| !(c.is_alphanumeric() || "_.$:".contains(c)))
+            .unwrap_or(self.code.len() - span.end);
+        span.start..span.end + len
+    }
+
+    fn unexpected(&self, span: Range<usize>, expected: &str) -> (Range<usize>, String) {
+        let span = self.word(span);
+        let msg = format!(
+            "expected {} but got `{}`",
+            expected,
+            &self.code[span.clone()]
+        );
+        (span, msg)
+    }
 }
 
-fn get_dest(token: Token) -> Dest {
+pub(super) fn get_dest(token: &Token) -> Option<Dest> {
     match token {
-        Token::A => Dest::A,
-        Token::D => Dest::D,
-        Token::M => Dest::M,
-        Token::AD => Dest::AD,
-        Token::AM => Dest::AM,
-        Token::MD => Dest::MD,
-        Token::AMD => Dest::AMD,
-        _ => Dest::Null,
+        Token::A => Some(Dest::A),
+        Token::D => Some(Dest::D),
+        Token::M => Some(Dest::M),
+        Token::AD => Some(Dest::AD),
+        Token::AM => Some(Dest::AM),
+        Token::MD => Some(Dest::MD),
+        Token::AMD => Some(Dest::AMD),
+        _ => None,
     }
 }
 
-fn get_jump(token: Token) -> super::Jump {
+pub(super) fn get_jump(token: &Token) -> Option<super::Jump> {
     match token {
-        Token::JGT => super::Jump::JGT,
-        Token::JEQ => super::Jump::JEQ,
-        Token::JGE => super::Jump::JGE,
-        Token::JLT => super::Jump::JLT,
-        Token::JNE => super::Jump::JNE,
-        Token::JLE => super::Jump::JLE,
-        Token::JMP => super::Jump::JMP,
-        _ => super::Jump::Null,
+        Token::JGT => Some(super::Jump::JGT),
+        Token::JEQ => Some(super::Jump::JEQ),
+        Token::JGE => Some(super::Jump::JGE),
+        Token::JLT => Some(super::Jump::JLT),
+        Token::JNE => Some(super::Jump::JNE),
+        Token::JLE => Some(super::Jump::JLE),
+        Token::JMP => Some(super::Jump::JMP),
+        _ => None,
     }
 }
 
-fn get_comp(token: Token) -> Result<Comp, String> {
+pub(super) fn get_comp(token: &Token) -> Option<Comp> {
     match token {
-        Token::Zero => Ok(Comp::Zero),
-        Token::One => Ok(Comp::One),
-        Token::MinusOne => Ok(Comp::MinusOne),
-        Token::D => Ok(Comp::D),
-        Token::A => Ok(Comp::A),
-        Token::NotD => Ok(Comp::NotD),
-        Token::NotA => Ok(Comp::NotA),
-        Token::MinusD => Ok(Comp::MinusD),
-        Token::MinusA => Ok(Comp::MinusA),
-        Token::DPulsOne => Ok(Comp::DPulsOne),
-        Token::APulsOne => Ok(Comp::APulsOne),
-        Token::DMinusOne => Ok(Comp::DMinusOne),
-        Token::AMinusOne => Ok(Comp::AMinusOne),
-        Token::DPulsA => Ok(Comp::DPulsA),
-        Token::DMinusA => Ok(Comp::DMinusA),
-        Token::AMinusD => Ok(Comp::AMinusD),
-        Token::DAndA => Ok(Comp::DAndA),
-        Token::DOrA => Ok(Comp::DOrA),
-        Token::M => Ok(Comp::M),
-        Token::NotM => Ok(Comp::NotM),
-        Token::MPlusOne => Ok(Comp::MPlusOne),
-        Token::MMinusOne => Ok(Comp::MMinusOne),
-        Token::DPulsM => Ok(Comp::DPulsM),
-        Token::DMinusM => Ok(Comp::DMinusM),
-        Token::MMinusD => Ok(Comp::MMinusD),
-        Token::DAndM => Ok(Comp::DAndM),
-        Token::DOrM => Ok(Comp::DOrM),
-        _ => Err(format!("expected Comp but got {:?}", token)),
+        Token::Zero => Some(Comp::Zero),
+        Token::One => Some(Comp::One),
+        Token::MinusOne => Some(Comp::MinusOne),
+        Token::D => Some(Comp::D),
+        Token::A => Some(Comp::A),
+        Token::NotD => Some(Comp::NotD),
+        Token::NotA => Some(Comp::NotA),
+        Token::MinusD => Some(Comp::MinusD),
+        Token::MinusA => Some(Comp::MinusA),
+        Token::DPulsOne => Some(Comp::DPulsOne),
+        Token::APulsOne => Some(Comp::APulsOne),
+        Token::DMinusOne => Some(Comp::DMinusOne),
+        Token::AMinusOne => Some(Comp::AMinusOne),
+        Token::DPulsA => Some(Comp::DPulsA),
+        Token::DMinusA => Some(Comp::DMinusA),
+        Token::AMinusD => Some(Comp::AMinusD),
+        Token::DAndA => Some(Comp::DAndA),
+        Token::DOrA => Some(Comp::DOrA),
+        Token::M => Some(Comp::M),
+        Token::NotM => Some(Comp::NotM),
+        Token::MinusM => Some(Comp::MinusM),
+        Token::MPlusOne => Some(Comp::MPlusOne),
+        Token::MMinusOne => Some(Comp::MMinusOne),
+        Token::DPulsM => Some(Comp::DPulsM),
+        Token::DMinusM => Some(Comp::DMinusM),
+        Token::MMinusD => Some(Comp::MMinusD),
+        Token::DAndM => Some(Comp::DAndM),
+        Token::DOrM => Some(Comp::DOrM),
+        _ => None,
     }
 }
 
 #[derive(Logos, Debug, Clone, PartialEq)]
-enum Token {
+pub(super) enum Token {
     #[token("0")]
     Zero,
     #[token("1")]
@@ -274,9 +527,9 @@ enum Token {
     DPulsOne,
     #[token("A+1")]
     APulsOne,
-    #[token("A-1")]
-    DMinusOne,
     #[token("D-1")]
+    DMinusOne,
+    #[token("A-1")]
     AMinusOne,
     #[token("D+A")]
     #[token("A+D")]
@@ -295,6 +548,8 @@ enum Token {
     M,
     #[token("!M")]
     NotM,
+    #[token("-M")]
+    MinusM,
     #[token("M+1")]
     MPlusOne,
     #[token("M-1")]
@@ -356,11 +611,11 @@ enum Token {
     #[regex(r"(/\*([^*]|\*[^/])*\*/)|(//[^\r\n]*(\r\n|\n)?)", ignore)]
     Ignore((usize, Option<String>)),
 
-    #[regex(r"@[a-zA-Z][a-zA-Z|0-9|\.|_|$]+", name)]
+    #[regex(r"@[a-zA-Z_.$:][a-zA-Z0-9_.$:]*", name)]
     Name(String),
     #[regex(r"@[0-9]+", number)]
     Number(usize),
-    #[regex(r"\([a-zA-Z][a-zA-Z|0-9|\.|_|$]+\)", label)]
+    #[regex(r"\([a-zA-Z_.$:][a-zA-Z0-9_.$:]*\)", label)]
     Labal(String),
 
     #[error]
@@ -415,6 +670,7 @@ fn ignore(lexer: &mut Lexer<Token>) -> Option<(usize, Option<String>)> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use tokenizer::Tokenizer;
 
     #[test]
     fn tokens() {
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// the cycles spent between a label and the next label in rom
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelTotal {
+    pub label: String,
+    /// address range `start..end` owned by the label
+    pub start: usize,
+    pub end: usize,
+    pub cycles: usize,
+}
+
+/// a backward jump from `end` to `start` that was taken at least once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HotLoop {
+    pub start: usize,
+    /// address of the jump instruction that closes the loop
+    pub end: usize,
+    /// the label whose region contains `start`
+    pub label: Option<String>,
+    /// how often the backward jump was taken
+    pub iterations: usize,
+    /// cycles spent on the instructions `start..=end`
+    pub cycles: usize,
+}
+
+/// execution counts per rom address, collected by [`crate::cpu::HackCpu::enable_profile`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    counts: Vec<usize>,
+    /// taken backward jumps, `(from, to) -> count`
+    back_edges: HashMap<(usize, usize), usize>,
+}
+
+impl Profile {
+    pub fn new(rom_len: usize) -> Self {
+        Self {
+            counts: vec![0; rom_len],
+            back_edges: HashMap::new(),
+        }
+    }
+
+    /// counts the instruction at `pc`, `next_pc` is where it went
+    pub(crate) fn record(&mut self, pc: usize, next_pc: usize) {
+        self.counts[pc] += 1;
+        if next_pc <= pc {
+            *self.back_edges.entry((pc, next_pc)).or_insert(0) += 1;
+        }
+    }
+
+    /// how often the instruction at `addr` was executed
+    pub fn count(&self, addr: usize) -> usize {
+        self.counts.get(addr).copied().unwrap_or(0)
+    }
+
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+
+    /// number of executed instructions
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.iter_mut().for_each(|c| *c = 0);
+        self.back_edges.clear();
+    }
+
+    /// cycles per label as returned by [`crate::cpu::parse_with_labels`], sorted by cycles.
+    /// instructions before the first label are reported under `""`
+    pub fn label_totals(&self, labels: &HashMap<String, usize>) -> Vec<LabelTotal> {
+        let mut totals: Vec<LabelTotal> = regions(labels, self.counts.len())
+            .into_iter()
+            .map(|(label, start, end)| LabelTotal {
+                cycles: self.counts[start..end].iter().sum(),
+                label,
+                start,
+                end,
+            })
+            .collect();
+        totals.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.start.cmp(&b.start)));
+        totals
+    }
+
+    /// like [`Profile::label_totals`] but labels like `Foo.bar$LOOP` are added to the function `Foo.bar`,
+    /// which is how the vm translator names labels inside a function
+    pub fn function_totals(&self, labels: &HashMap<String, usize>) -> Vec<(String, usize)> {
+        let mut functions: HashMap<String, usize> = HashMap::new();
+        for total in self.label_totals(labels) {
+            *functions
+                .entry(function(&total.label).to_string())
+                .or_insert(0) += total.cycles;
+        }
+        let mut functions: Vec<(String, usize)> = functions.into_iter().collect();
+        functions.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        functions
+    }
+
+    /// loops found by their backward jumps, sorted by cycles. jumps that leave the function of the jump
+    /// target, like calls of shared helpers or returns, are not loops
+    pub fn hot_loops(&self, labels: &HashMap<String, usize>) -> Vec<HotLoop> {
+        let regions = regions(labels, self.counts.len());
+        let label_at = |addr: usize| {
+            regions
+                .iter()
+                .find(|(_, start, end)| (*start..*end).contains(&addr))
+                .map_or("", |(label, _, _)| label.as_str())
+        };
+        let mut loops: Vec<HotLoop> = self
+            .back_edges
+            .iter()
+            .filter(|(&(end, start), _)| function(label_at(start)) == function(label_at(end)))
+            .map(|(&(end, start), &iterations)| HotLoop {
+                start,
+                end,
+                label: Some(label_at(start))
+                    .filter(|label| !label.is_empty())
+                    .map(str::to_string),
+                iterations,
+                cycles: self.counts[start..=end].iter().sum(),
+            })
+            .collect();
+        loops.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.start.cmp(&b.start)));
+        loops
+    }
+
+    /// a human readable summary with the `top` hottest loops, functions and labels
+    pub fn report(&self, labels: &HashMap<String, usize>, top: usize) -> String {
+        let total = self.total().max(1);
+        let percent = |cycles: usize| cycles as f64 * 100.0 / total as f64;
+        let mut text = String::new();
+
+        writeln!(text, "{} instructions executed", self.total()).unwrap();
+
+        writeln!(text, "\nhot loops").unwrap();
+        for l in self.hot_loops(labels).iter().take(top) {
+            writeln!(
+                text,
+                "{:>12} {:>6.2}%  {:>5}..={:<5} {:>10} iterations  {}",
+                l.cycles,
+                percent(l.cycles),
+                l.start,
+                l.end,
+                l.iterations,
+                l.label.as_deref().unwrap_or("")
+            )
+            .unwrap();
+        }
+
+        writeln!(text, "\nfunctions").unwrap();
+        for (function, cycles) in self.function_totals(labels).iter().take(top) {
+            writeln!(
+                text,
+                "{:>12} {:>6.2}%  {}",
+                cycles,
+                percent(*cycles),
+                function
+            )
+            .unwrap();
+        }
+
+        writeln!(text, "\nlabels").unwrap();
+        for l in self.label_totals(labels).iter().take(top) {
+            writeln!(
+                text,
+                "{:>12} {:>6.2}%  {:>5}..{:<5} {}",
+                l.cycles,
+                percent(l.cycles),
+                l.start,
+                l.end,
+                l.label
+            )
+            .unwrap();
+        }
+        text
+    }
+}
+
+/// `Foo.bar` for a label `Foo.bar$LOOP`
+fn function(label: &str) -> &str {
+    label
+        .split_once('$')
+        .map_or(label, |(function, _)| function)
+}
+
+/// splits `0..rom_len` at the labels, labels at the same address share an empty region except the last one
+fn regions(labels: &HashMap<String, usize>, rom_len: usize) -> Vec<(String, usize, usize)> {
+    let mut sorted: Vec<(&String, usize)> = labels
+        .iter()
+        .map(|(label, &addr)| (label, addr.min(rom_len)))
+        .collect();
+    sorted.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(b.0)));
+
+    let mut regions = Vec::new();
+    let first = sorted.first().map_or(rom_len, |&(_, addr)| addr);
+    if first > 0 {
+        regions.push((String::new(), 0, first));
+    }
+    for (i, &(label, start)) in sorted.iter().enumerate() {
+        let end = sorted.get(i + 1).map_or(rom_len, |&(_, addr)| addr);
+        regions.push((label.clone(), start, end));
+    }
+    regions
+}
@@ -0,0 +1,69 @@
+use super::CPUInstruction;
+use std::collections::HashMap;
+
+/// a position in the assembly source, both counting from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourcePos {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourcePos {
+    /// the positions of the byte `offsets` in `code`, the offsets have to be sorted. columns count chars
+    pub fn from_offsets(code: &str, offsets: &[usize]) -> Vec<Self> {
+        let mut positions = Vec::with_capacity(offsets.len());
+        let mut pos = SourcePos { line: 1, column: 1 };
+        let mut chars = code.char_indices().peekable();
+        for &offset in offsets {
+            while let Some((_, c)) = chars.next_if(|&(i, _)| i < offset) {
+                if c == '\n' {
+                    pos.line += 1;
+                    pos.column = 1;
+                } else {
+                    pos.column += 1;
+                }
+            }
+            positions.push(pos);
+        }
+        positions
+    }
+}
+
+/// everything the assembler knows about a program, as returned by [`crate::cpu::parse_program`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmProgram {
+    pub instructions: Vec<CPUInstruction>,
+    /// rom address of every label defined in the code
+    pub labels: HashMap<String, usize>,
+    /// ram address of every variable, allocated from the statics of the machine upwards in order of
+    /// first use
+    pub variables: HashMap<String, usize>,
+    /// rom addresses of the a-instructions that load the address of a label, in ascending order.
+    /// these have to change when instructions move
+    pub relocations: Vec<usize>,
+    /// where the instruction at each rom address starts in the source
+    pub source_map: Vec<SourcePos>,
+}
+
+impl AsmProgram {
+    pub fn source_pos(&self, addr: usize) -> Option<SourcePos> {
+        self.source_map.get(addr).copied()
+    }
+
+    /// the labels defined at `addr`, sorted by name
+    pub fn labels_at(&self, addr: usize) -> Vec<&str> {
+        let mut labels: Vec<&str> = self
+            .labels
+            .iter()
+            .filter(|(_, &a)| a == addr)
+            .map(|(label, _)| label.as_str())
+            .collect();
+        labels.sort_unstable();
+        labels
+    }
+
+    /// the rom address of the first instruction that starts on `line`
+    pub fn addr_of_line(&self, line: usize) -> Option<usize> {
+        self.source_map.iter().position(|pos| pos.line == line)
+    }
+}
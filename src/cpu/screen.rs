@@ -0,0 +1,151 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const SCREEN_WIDTH: usize = 512;
+pub const SCREEN_HEIGHT: usize = 256;
+
+/// number of ram words the screen is mapped to
+pub const SCREEN_WORDS: usize = SCREEN_WIDTH * SCREEN_HEIGHT / 16;
+
+/// snapshot of the memory mapped screen, every row is 32 words and bit 0 of a word is its leftmost pixel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screen {
+    words: Vec<i16>,
+}
+
+impl Screen {
+    /// `words` is the screen memory map, missing words are treated as white
+    pub fn from_ram(words: &[i16]) -> Self {
+        let mut words = words[..words.len().min(SCREEN_WORDS)].to_vec();
+        words.resize(SCREEN_WORDS, 0);
+        Self { words }
+    }
+
+    pub fn words(&self) -> &[i16] {
+        &self.words
+    }
+
+    /// true if the pixel is black
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        assert!(
+            x < SCREEN_WIDTH && y < SCREEN_HEIGHT,
+            "pixel ({}, {}) is off screen",
+            x,
+            y
+        );
+        self.words[y * SCREEN_WIDTH / 16 + x / 16] & (1 << (x % 16)) != 0
+    }
+
+    pub fn black_pixels(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// one row as bytes with the leftmost pixel in the most significant bit
+    fn row(&self, y: usize) -> impl Iterator<Item = u8> + '_ {
+        self.words[y * SCREEN_WIDTH / 16..(y + 1) * SCREEN_WIDTH / 16]
+            .iter()
+            .flat_map(|&w| {
+                let w = (w as u16).reverse_bits();
+                [(w >> 8) as u8, w as u8]
+            })
+    }
+
+    /// binary portable bitmap (P4)
+    pub fn to_pbm(&self) -> Vec<u8> {
+        let mut pbm = format!("P4\n{} {}\n", SCREEN_WIDTH, SCREEN_HEIGHT).into_bytes();
+        for y in 0..SCREEN_HEIGHT {
+            pbm.extend(self.row(y));
+        }
+        pbm
+    }
+
+    /// 1 bit grayscale png, stored without compression
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::new();
+        for y in 0..SCREEN_HEIGHT {
+            // filter type none, png uses 0 for black
+            raw.push(0);
+            raw.extend(self.row(y).map(|b| !b));
+        }
+
+        let mut ihdr = Vec::new();
+        ihdr.extend((SCREEN_WIDTH as u32).to_be_bytes());
+        ihdr.extend((SCREEN_HEIGHT as u32).to_be_bytes());
+        // bit depth 1, grayscale, deflate, no filter, no interlace
+        ihdr.extend([1, 0, 0, 0, 0]);
+
+        let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+        png_chunk(&mut png, b"IHDR", &ihdr);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    pub fn save_pbm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_pbm())
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_png())
+    }
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+/// zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut chunks = data.chunks(0xffff).peekable();
+    while let Some(chunk) = chunks.next() {
+        out.push(if chunks.peek().is_none() { 1 } else { 0 });
+        let len = chunk.len() as u16;
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(chunk);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+}
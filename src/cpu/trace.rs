@@ -0,0 +1,106 @@
+use super::{asm2ml, CPUInstruction, MemoryAccess};
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// the state after one executed instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub cycle: usize,
+    /// address of the executed instruction
+    pub pc: usize,
+    pub instruction: CPUInstruction,
+    pub a_reg: i16,
+    pub d_reg: i16,
+    pub write: Option<MemoryAccess>,
+}
+
+/// ring buffer that keeps the last `capacity` executed instructions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl Trace {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, entry: TraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+
+    pub fn last(&self) -> Option<&TraceEntry> {
+        self.entries.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// one line per entry, meant to be read or diffed by humans
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for e in self.entries.iter() {
+            write!(
+                text,
+                "{:>10} {:>5}  {:<12} A={:<6} D={:<6}",
+                e.cycle,
+                e.pc,
+                e.instruction.to_string(),
+                e.a_reg,
+                e.d_reg
+            )
+            .unwrap();
+            if let Some(w) = e.write {
+                write!(text, " RAM[{}]: {} -> {}", w.addr, w.old, w.new).unwrap();
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// the instruction is written as its 16 bit machine code, columns without a write are empty
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("cycle,pc,instruction,a,d,addr,old,new\n");
+        for e in self.entries.iter() {
+            let word = asm2ml(vec![e.instruction.clone()])[0];
+            write!(
+                csv,
+                "{},{},{:016b},{},{}",
+                e.cycle, e.pc, word, e.a_reg, e.d_reg
+            )
+            .unwrap();
+            match e.write {
+                Some(w) => writeln!(csv, ",{},{},{}", w.addr, w.old, w.new).unwrap(),
+                None => csv.push_str(",,,\n"),
+            }
+        }
+        csv
+    }
+}
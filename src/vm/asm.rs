@@ -1,13 +1,18 @@
 use super::{Segment, VMInstruction};
-use crate::cpu::{CPUInstruction, Comp, Dest, Jump};
+use crate::cpu::{CPUInstruction, Comp, Dest, Jump, MachineProfile};
 
 pub fn vm2asm(instrucs: Vec<VMInstruction>) -> Vec<CPUInstruction> {
+    vm2asm_for(instrucs, &MachineProfile::default())
+}
+
+/// like [`vm2asm`] with the static and temp segments of `machine`
+pub fn vm2asm_for(instrucs: Vec<VMInstruction>, machine: &MachineProfile) -> Vec<CPUInstruction> {
     let mut asm = Vec::new();
 
     for instruc in instrucs {
         match instruc {
             VMInstruction::Push(seg, value) => {
-                let value = seg2addr(seg) as i16 + value;
+                let value = seg2addr(seg, machine) as i16 + value;
                 asm.push(CPUInstruction::AInstruc(value)); // @value
                 asm.push(CPUInstruction::CInstruc(Comp::M, Dest::A, Jump::Null)); // A=M
                 push(&mut asm);
@@ -131,13 +136,13 @@ fn top2d(asm: &mut Vec<CPUInstruction>) {
     )); // M=M-1
 }
 
-fn seg2addr(seg: Segment) -> usize {
+fn seg2addr(seg: Segment, machine: &MachineProfile) -> usize {
     match seg {
         Segment::Argument => crate::ARG,
         Segment::Local => crate::LCL,
         Segment::Pointer => crate::PTR,
-        Segment::Static => crate::STATIC,
-        Segment::Temp => crate::TEMP,
+        Segment::Static => machine.statics,
+        Segment::Temp => machine.temp,
         Segment::That => crate::THAT,
         Segment::This => crate::THIS,
     }
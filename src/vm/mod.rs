@@ -2,7 +2,7 @@ mod asm;
 mod jack_vm;
 mod parser;
 
-pub use asm::vm2asm;
+pub use asm::{vm2asm, vm2asm_for};
 pub use jack_vm::JackVM;
 pub use parser::parse;
 
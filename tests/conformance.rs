@@ -0,0 +1,237 @@
+use n2t_lib::cpu::{asm2ml, assemble, parse, parse_program, str2ml, HackCpu};
+use std::collections::HashMap;
+use std::fs::read_to_string;
+
+/// the rows of a `.cmp` file, `|` separated integers below a header of `RAM[addr]` columns
+fn read_cmp(path: &str) -> (Vec<usize>, Vec<Vec<i16>>) {
+    let cmp = read_to_string(path).unwrap();
+    let mut lines = cmp.lines().filter(|line| !line.trim().is_empty());
+    let cells = |line: &str| -> Vec<String> {
+        line.split('|')
+            .map(|cell| cell.trim().to_string())
+            .filter(|cell| !cell.is_empty())
+            .collect()
+    };
+
+    let header = cells(lines.next().unwrap())
+        .iter()
+        .map(|cell| cell["RAM[".len()..cell.len() - 1].parse().unwrap())
+        .collect();
+    let rows = lines
+        .map(|line| cells(line).iter().map(|c| c.parse().unwrap()).collect())
+        .collect();
+    (header, rows)
+}
+
+fn assemble_06(name: &str) {
+    let dir = format!("tests/projects/06/{}", name.to_lowercase());
+    let hack = str2ml(&read_to_string(format!("{}/{}.hack", dir, name)).unwrap()).unwrap();
+
+    let mut files = vec![format!("{}/{}.asm", dir, name)];
+    let symbol_less = format!("{}/{}L.asm", dir, name);
+    if std::path::Path::new(&symbol_less).exists() {
+        files.push(symbol_less);
+    }
+    for file in files {
+        let assembly = assemble(&read_to_string(&file).unwrap());
+        assert!(!assembly.has_errors(), "{}", file);
+        let program = assembly.program.unwrap();
+        assert_eq!(asm2ml(program.instructions), hack, "{}", file);
+    }
+}
+
+#[test]
+fn project_06_add() {
+    assemble_06("Add");
+}
+
+#[test]
+fn project_06_max() {
+    assemble_06("Max");
+}
+
+#[test]
+fn project_06_rect() {
+    assemble_06("Rect");
+}
+
+#[test]
+fn project_06_pong() {
+    assemble_06("Pong");
+}
+
+/// the files in project 04 are templates to be filled in, they have to assemble to an empty program
+#[test]
+fn project_04_templates() {
+    for file in [
+        "tests/projects/04/mult/Mult.asm",
+        "tests/projects/04/fill/Fill.asm",
+    ] {
+        let assembly = assemble(&read_to_string(file).unwrap());
+        assert!(assembly.diagnostics.is_empty(), "{}", file);
+        assert!(
+            assembly.program.unwrap().instructions.is_empty(),
+            "{}",
+            file
+        );
+    }
+}
+
+const MULT: &str = r"
+    @R2
+    M=0
+    @R0
+    D=M
+    @i
+    M=D
+(LOOP)
+    @i
+    D=M
+    @END
+    D;JEQ
+    @R1
+    D=M
+    @R2
+    M=D+M
+    @i
+    M=M-1
+    @LOOP
+    0;JMP
+(END)
+    @END
+    0;JMP
+";
+
+/// replays Mult.tst, every scenario runs at most the number of cycles the test script gives it
+#[test]
+fn project_04_mult() {
+    let (header, rows) = read_cmp("tests/projects/04/mult/Mult.cmp");
+    assert_eq!(header, vec![0, 1, 2]);
+    let budgets = [20, 50, 80, 120, 150, 210];
+    assert_eq!(rows.len(), budgets.len());
+
+    let mut cpu = HackCpu::new(parse(MULT).unwrap());
+    for (row, budget) in rows.iter().zip(budgets) {
+        cpu.reset();
+        cpu.set_ram_range(0, &[row[0], row[1], -1]);
+        cpu.run(budget);
+        assert_eq!(&cpu.ram_range(0..3), row);
+    }
+}
+
+const FILL: &str = r"
+(LOOP)
+    @KBD
+    D=M
+    @c
+    M=0
+    @DRAW
+    D;JEQ
+    @c
+    M=-1
+(DRAW)
+    @SCREEN
+    D=A
+    @p
+    M=D
+(FILL)
+    @c
+    D=M
+    @p
+    A=M
+    M=D
+    @p
+    MD=M+1
+    @KBD
+    D=D-A
+    @FILL
+    D;JLT
+    @LOOP
+    0;JMP
+";
+
+/// replays FillAutomatic.tst, which sets the keyboard register directly
+#[test]
+fn project_04_fill() {
+    let (header, rows) = read_cmp("tests/projects/04/fill/FillAutomatic.cmp");
+    let keys = [0, 1, 0];
+    assert_eq!(rows.len(), keys.len());
+
+    let mut cpu = HackCpu::new(parse(FILL).unwrap());
+    for (row, key) in rows.iter().zip(keys) {
+        cpu.set_ram(24576, key);
+        cpu.run(1_000_000);
+        let screen: Vec<i16> = header.iter().map(|&addr| cpu.ram(addr)).collect();
+        assert_eq!(&screen, row);
+    }
+}
+
+#[test]
+fn symbol_grammar() {
+    let code = r"
+        @i
+        @_
+        @.
+        @$
+        @:
+        @a1
+        @x_y.z$w:v
+        @.9
+    (X)
+        @X
+    ($)
+        @$
+    (:start.2)
+        @:start.2
+        @R16
+        @R15
+    ";
+    let program = parse_program(code).unwrap();
+    assert_eq!(
+        asm2ml(program.instructions),
+        vec![16, 17, 18, 9, 19, 20, 21, 22, 8, 9, 10, 23, 15]
+    );
+    assert_eq!(
+        program.labels,
+        HashMap::from([
+            ("X".to_string(), 8),
+            ("$".to_string(), 9),
+            (":start.2".to_string(), 10)
+        ])
+    );
+    assert_eq!(program.variables.len(), 8);
+    assert_eq!(program.variables["R16"], 23);
+}
+
+/// the predefined symbols of figure 6.2 and nothing else
+#[test]
+fn predefined_symbols() {
+    let mut expected: HashMap<String, i16> = (0..16).map(|i| (format!("R{}", i), i)).collect();
+    for (symbol, val) in [
+        ("SP", 0),
+        ("LCL", 1),
+        ("ARG", 2),
+        ("THIS", 3),
+        ("THAT", 4),
+        ("SCREEN", 16384),
+        ("KBD", 24576),
+    ] {
+        expected.insert(symbol.to_string(), val);
+    }
+
+    for (symbol, val) in expected {
+        let program = parse_program(&format!("@{}", symbol)).unwrap();
+        assert!(program.variables.is_empty(), "{}", symbol);
+        assert_eq!(asm2ml(program.instructions), vec![val as u16], "{}", symbol);
+    }
+
+    for symbol in ["R16", "R", "sp", "Screen", "R0a", "KBD1"] {
+        let program = parse_program(&format!("@{}", symbol)).unwrap();
+        assert_eq!(
+            program.variables,
+            HashMap::from([(symbol.to_string(), 16)]),
+            "{}",
+            symbol
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use n2t_lib::cpu::{parse, parse_program, AsmProgram, Coverage, HackCpu};
+
+/// sets R1 to -1 if R0 is not positive
+const SIGN: &str = "\
+@R0
+D=M
+@POS
+D;JGT
+@R1
+M=-1
+(POS)
+@END
+0;JMP
+(END)
+@END
+0;JMP
+";
+
+const MULT: &str = "\
+    @R2
+    M=0
+(LOOP)
+    @R1
+    D=M
+    @END
+    D;JLE
+    @R0
+    D=M
+    @R2
+    M=D+M
+    @R1
+    M=M-1
+    @LOOP
+    0;JMP
+(END)
+    @END
+    0;JMP
+";
+
+/// runs `program` once for every `(R0, R1)` like a test script does and returns the coverage
+fn run(program: &AsmProgram, cases: &[(i16, i16)]) -> Coverage {
+    let mut cpu = HackCpu::from_program(program);
+    cpu.enable_coverage();
+    for &(r0, r1) in cases {
+        cpu.reset();
+        cpu.set_ram_range(0, &[r0, r1, -1]);
+        cpu.run(1000);
+    }
+    cpu.disable_coverage().unwrap()
+}
+
+#[test]
+fn lcov() {
+    let program = parse_program(SIGN).unwrap();
+    let coverage = run(&program, &[(5, 0)]);
+    assert_eq!(coverage.branch(3), Some((1, 0)));
+    assert_eq!(coverage.branch(2), None);
+    assert_eq!(coverage.uncovered(), [4, 5, 9]);
+    assert_eq!(
+        coverage.lcov(&program, "Sign.asm"),
+        "TN:
+SF:Sign.asm
+BRDA:4,3,0,1
+BRDA:4,3,1,0
+BRF:2
+BRH:1
+DA:1,1
+DA:2,1
+DA:3,1
+DA:4,1
+DA:5,0
+DA:6,0
+DA:8,1
+DA:9,1
+DA:11,1
+DA:12,0
+LF:10
+LH:7
+end_of_record
+"
+    );
+
+    let coverage = run(&program, &[(5, 0), (-1, 0)]);
+    assert!(coverage.partial_branches().is_empty());
+    let lcov = coverage.lcov(&program, "Sign.asm");
+    assert!(lcov.contains("BRF:2\nBRH:2\n"));
+    assert!(lcov.contains("\nLF:10\nLH:9\n"));
+}
+
+#[test]
+fn mult_test_script() {
+    let program = parse_program(MULT).unwrap();
+    // the cases of Mult.tst
+    let coverage = run(&program, &[(0, 0), (1, 0), (0, 2), (3, 1), (2, 4), (6, 7)]);
+    assert!(coverage.partial_branches().is_empty());
+    assert_eq!(coverage.uncovered(), [15]);
+
+    // a test that only multiplies by 0 never runs the loop body
+    let coverage = run(&program, &[(0, 0), (1, 0)]);
+    assert_eq!(coverage.partial_branches(), [(5, (2, 0))]);
+    assert_eq!(coverage.uncovered(), [6, 7, 8, 9, 10, 11, 12, 13, 15]);
+    assert_eq!(coverage.hits(0), 2);
+}
+
+#[test]
+fn cobertura() {
+    let program = parse_program(SIGN).unwrap();
+    let coverage = run(&program, &[(5, 0)]);
+    let xml = coverage.cobertura(&program, "projects/Sign.asm");
+    assert!(xml.starts_with(
+        "<?xml version=\"1.0\" ?>\n<coverage line-rate=\"0.7\" branch-rate=\"0.5\" \
+         lines-covered=\"7\" lines-valid=\"10\" branches-covered=\"1\" branches-valid=\"2\""
+    ));
+    assert!(xml.contains("<class name=\"Sign\" filename=\"projects/Sign.asm\""));
+    assert!(xml.contains(
+        "<line number=\"4\" hits=\"1\" branch=\"true\" condition-coverage=\"50% (1/2)\"/>\n"
+    ));
+    assert!(xml.contains("<line number=\"5\" hits=\"0\" branch=\"false\"/>\n"));
+    assert!(xml.ends_with("</coverage>\n"));
+}
+
+#[test]
+fn merge_and_hack_lines() {
+    let program = parse_program(SIGN).unwrap();
+    let mut coverage = run(&program, &[(5, 0)]);
+    coverage.merge(&run(&program, &[(-1, 0)])).unwrap();
+    assert_eq!(coverage.branch(3), Some((1, 1)));
+    assert_eq!(coverage.hits(0), 2);
+    assert!(coverage
+        .merge(&Coverage::new(&parse("D=0").unwrap()))
+        .is_err());
+
+    // without a source map the lines are the ones of the .hack file
+    let stripped = AsmProgram {
+        source_map: Vec::new(),
+        ..program
+    };
+    assert!(coverage
+        .lcov(&stripped, "Sign.hack")
+        .contains("BRDA:4,3,0,1\nBRDA:4,3,1,1\n"));
+    assert!(coverage.lcov(&stripped, "Sign.hack").contains("DA:10,0\n"));
+
+    coverage.clear();
+    assert_eq!(coverage.uncovered().len(), 10);
+}
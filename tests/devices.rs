@@ -0,0 +1,176 @@
+use n2t_lib::cpu::{
+    parse, AccessKind, ConsoleOut, CycleCounter, Device, HackCpu, Key, Keyboard, Random,
+    RunOutcome, Watchpoint,
+};
+
+/// writes "Hi" and a newline to the console at 24577
+const HELLO: &str = "
+    @72
+    D=A
+    @24577
+    M=D
+    @105
+    D=A
+    @24577
+    M=D
+    @128
+    D=A
+    @24577
+    M=D
+(END)
+    @END
+    0;JMP
+";
+
+#[test]
+fn console() {
+    let mut cpu = HackCpu::new(parse(HELLO).unwrap());
+    let console = cpu.map_device(24577, ConsoleOut::new()).unwrap();
+    assert!(matches!(cpu.run(100), RunOutcome::Halted { .. }));
+    assert_eq!(cpu.device::<ConsoleOut>(console).unwrap().output(), "Hi\n");
+    assert_eq!(cpu.ram(24577), 0);
+    assert!(cpu.device::<Random>(console).is_none());
+
+    // the same with every instruction looked at
+    let mut cpu = HackCpu::new(parse(HELLO).unwrap());
+    let console = cpu.map_device(24577, ConsoleOut::new()).unwrap();
+    cpu.enable_trace(10);
+    assert!(matches!(cpu.run(100), RunOutcome::Halted { .. }));
+    assert_eq!(cpu.device::<ConsoleOut>(console).unwrap().output(), "Hi\n");
+
+    // without the device the writes end up in ram
+    let mut cpu = HackCpu::new(parse(HELLO).unwrap());
+    cpu.run(100);
+    assert_eq!(cpu.ram(24577), 128);
+}
+
+#[test]
+fn cycle_counter() {
+    let code = "
+        @24580
+        M=0
+        @5
+        D=A
+    (LOOP)
+        D=D-1
+        @LOOP
+        D;JGT
+        @24580
+        D=M
+        @R0
+        M=D
+    (END)
+        @END
+        0;JMP
+    ";
+    let mut cpu = HackCpu::new(parse(code).unwrap());
+    let counter = cpu.map_device(24580, CycleCounter::new()).unwrap();
+    cpu.run(1000);
+    // reset at cycle 1 and read at cycle 20
+    assert_eq!(cpu.ram(0), 19);
+    assert_eq!(cpu.ram(24581), 0);
+
+    let counter = cpu.device::<CycleCounter>(counter).unwrap();
+    assert_eq!(counter.peek(0, 0x12345 + 1), 0x2345);
+    assert_eq!(counter.peek(1, 0x12345 + 1), 1);
+}
+
+#[test]
+fn random() {
+    let code = "
+        @24590
+        D=M
+        @R0
+        M=D
+        @24590
+        D=M
+        @R1
+        M=D
+    (END)
+        @END
+        0;JMP
+    ";
+    let run = |seed: u16| {
+        let mut cpu = HackCpu::new(parse(code).unwrap());
+        cpu.map_device(24590, Random::new(seed)).unwrap();
+        cpu.run(100);
+        (cpu.ram(0), cpu.ram(1))
+    };
+    let (a, b) = run(1);
+    assert_ne!(a, b);
+    assert_eq!(run(1), (a, b));
+    assert_ne!(run(2), (a, b));
+    // peeking does not advance the generator
+    let mut cpu = HackCpu::new(parse(code).unwrap());
+    cpu.map_device(24590, Random::new(1)).unwrap();
+    assert_eq!(cpu.ram(24590), cpu.ram(24590));
+}
+
+#[test]
+fn mapping() {
+    let mut cpu = HackCpu::new(parse("D=0").unwrap());
+    let counter = cpu.map_device(24577, CycleCounter::new()).unwrap();
+    assert_eq!(
+        cpu.map_device(24578, ConsoleOut::new()).unwrap_err(),
+        "a device at 24578..24579 overlaps the one at 24577..24579"
+    );
+    assert_eq!(
+        cpu.map_device(0x7fff, CycleCounter::new()).unwrap_err(),
+        "a device at 32767..32769 does not fit into the 32768 addresses"
+    );
+    cpu.map_device(24579, ConsoleOut::new()).unwrap();
+
+    cpu.set_ram(100, 7);
+    assert!(cpu.unmap_device(counter).is_some());
+    assert!(cpu.unmap_device(counter).is_none());
+    cpu.map_device(24578, Random::default()).unwrap();
+    assert_eq!(cpu.ram(100), 7);
+}
+
+/// a device over the keyboard register gets the key codes
+#[test]
+fn keyboard_device() {
+    let code = "
+    (LOOP)
+        D=D+1
+        @LOOP
+        0;JMP
+    ";
+    let mut cpu = HackCpu::new(parse(code).unwrap());
+    let mut keyboard = Keyboard::new();
+    keyboard.press(3, Key::Char('A'));
+    cpu.set_keyboard(keyboard);
+    let console = cpu.map_device(24576, ConsoleOut::new()).unwrap();
+    cpu.run(10);
+    assert_eq!(cpu.device::<ConsoleOut>(console).unwrap().output(), "A");
+}
+
+#[test]
+fn clones_and_watchpoints() {
+    let mut cpu = HackCpu::new(parse(HELLO).unwrap());
+    let console = cpu.map_device(24577, ConsoleOut::new()).unwrap();
+    cpu.add_watchpoint(Watchpoint::Write(24577..24578));
+    assert!(matches!(cpu.run(100), RunOutcome::Watchpoint { .. }));
+    assert_eq!(cpu.last_write().unwrap().kind, AccessKind::Write);
+
+    let mut copy = cpu.clone();
+    copy.clear_breakpoints();
+    copy.run(100);
+    assert_eq!(copy.device::<ConsoleOut>(console).unwrap().output(), "Hi\n");
+    assert_eq!(cpu.device::<ConsoleOut>(console).unwrap().output(), "H");
+    cpu.device_mut::<ConsoleOut>(console).unwrap().clear();
+    assert_eq!(cpu.device::<ConsoleOut>(console).unwrap().output(), "");
+}
+
+/// undoing a write to a device leaves the ram below it alone
+#[test]
+fn step_back_over_device() {
+    let mut cpu = HackCpu::new(parse("@9\nD=A\n@24577\nM=D").unwrap());
+    cpu.set_ram(24577, 5);
+    let id = cpu.map_device(24577, CycleCounter::new()).unwrap();
+    cpu.enable_history(10);
+    cpu.run(4);
+    assert!(cpu.step_back());
+    cpu.unmap_device(id);
+    assert_eq!(cpu.ram(24577), 5);
+}
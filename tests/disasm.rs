@@ -0,0 +1,75 @@
+use n2t_lib::cpu::{asm2ml, disassemble, parse, str2ml, CPUInstruction, Comp, Dest, Jump};
+use std::fs::read_to_string;
+
+#[test]
+fn display() {
+    let cases = [
+        (CPUInstruction::AInstruc(21), "@21"),
+        (CPUInstruction::AInstruc(-1), "@32767"),
+        (
+            CPUInstruction::CInstruc(Comp::Zero, Dest::Null, Jump::JMP),
+            "0;JMP",
+        ),
+        (
+            CPUInstruction::CInstruc(Comp::DPulsM, Dest::AMD, Jump::JNE),
+            "AMD=D+M;JNE",
+        ),
+        (
+            CPUInstruction::CInstruc(Comp::MinusOne, Dest::M, Jump::Null),
+            "M=-1",
+        ),
+        (
+            CPUInstruction::CInstruc(Comp::DOrA, Dest::Null, Jump::Null),
+            "D|A",
+        ),
+        (
+            CPUInstruction::CInstruc(Comp::from_bits(0b0000001), Dest::D, Jump::Null),
+            "D=0b0000001",
+        ),
+    ];
+    for (instruction, text) in cases {
+        assert_eq!(instruction.to_string(), text);
+    }
+
+    // every documented instruction prints as something the parser reads back
+    let code = "@21\nAMD=D+M;JNE\nM=-1\nD|A\n0;JMP\n";
+    let text: Vec<String> = parse(code).unwrap().iter().map(|i| i.to_string()).collect();
+    assert_eq!(text.join("\n") + "\n", code);
+}
+
+#[test]
+fn max() {
+    let ml = str2ml(&read_to_string("tests/projects/06/max/Max.hack").unwrap()).unwrap();
+    assert_eq!(
+        disassemble(&ml).unwrap(),
+        "    @R0\n    D=M\n    @R1\n    D=D-M\n    @L10\n    D;JGT\n    @R1\n    D=M\n    @L12\n    0;JMP\n\
+         (L10)\n    @R0\n    D=M\n(L12)\n    @R2\n    M=D\n(L14)\n    @L14\n    0;JMP\n"
+    );
+}
+
+#[test]
+fn round_trip() {
+    for name in ["add/Add", "max/Max", "rect/Rect", "pong/Pong"] {
+        let hack = read_to_string(format!("tests/projects/06/{}.hack", name)).unwrap();
+        let ml = str2ml(&hack).unwrap();
+        let asm = disassemble(&ml).unwrap();
+        assert_eq!(asm2ml(parse(&asm).unwrap()), ml, "{}", name);
+    }
+}
+
+#[test]
+fn labels() {
+    // a jump to the end of the rom and one past it
+    let ml = asm2ml(parse("@2\n0;JMP\n@10\nD;JEQ\n@11\nD;JLT\n@16384\nD=A\n@24576\nM=0").unwrap());
+    assert_eq!(
+        disassemble(&ml).unwrap(),
+        "    @L2\n    0;JMP\n(L2)\n    @L10\n    D;JEQ\n    @11\n    D;JLT\n    @16384\n    D=A\n    @KBD\n    M=0\n(L10)\n"
+    );
+}
+
+#[test]
+fn errors() {
+    assert!(disassemble(&[0b1000_1100_0001_0000]).is_err());
+    assert!(disassemble(&[0b1110_0000_0101_0000]).is_err());
+    assert_eq!(disassemble(&[]), Ok(String::new()));
+}
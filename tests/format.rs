@@ -0,0 +1,102 @@
+use n2t_lib::cpu::{format_asm, parse, SyntaxKind, SyntaxTree};
+use std::fs::read_to_string;
+
+const FILES: [&str; 9] = [
+    "tests/projects/04/mult/Mult.asm",
+    "tests/projects/04/fill/Fill.asm",
+    "tests/projects/06/add/Add.asm",
+    "tests/projects/06/max/Max.asm",
+    "tests/projects/06/max/MaxL.asm",
+    "tests/projects/06/rect/Rect.asm",
+    "tests/projects/06/rect/RectL.asm",
+    "tests/projects/06/pong/Pong.asm",
+    "tests/projects/06/pong/PongL.asm",
+];
+
+#[test]
+fn lossless() {
+    for file in FILES {
+        let code = read_to_string(file).unwrap();
+        let tree = SyntaxTree::parse(&code);
+        assert_eq!(tree.errors().count(), 0, "{}", file);
+        assert_eq!(tree.to_string(), code, "{}", file);
+    }
+
+    let code = "\t(LOOP)  AM = M+1 ; JMP // x\r\n/* a\nb */@i\n\nD=Q\n";
+    assert_eq!(SyntaxTree::parse(code).to_string(), code);
+}
+
+#[test]
+fn nodes() {
+    let tree = SyntaxTree::parse("  AM = M+1 ; JMP // x\n");
+    let kinds: Vec<SyntaxKind> = tree.nodes.iter().map(|n| n.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            SyntaxKind::Whitespace,
+            SyntaxKind::Whitespace,
+            SyntaxKind::CInstruction,
+            SyntaxKind::Whitespace,
+            SyntaxKind::Comment,
+            SyntaxKind::Newline,
+        ]
+    );
+
+    let instruction = &tree.nodes[2];
+    assert_eq!(instruction.text, "AM = M+1 ; JMP");
+    assert_eq!(instruction.span, 2..16);
+    let parts: Vec<(SyntaxKind, &str)> = instruction
+        .children
+        .iter()
+        .filter(|n| !n.is_trivia())
+        .map(|n| (n.kind, n.text.as_str()))
+        .collect();
+    assert_eq!(
+        parts,
+        vec![
+            (SyntaxKind::Dest, "AM"),
+            (SyntaxKind::Equals, "="),
+            (SyntaxKind::Comp, "M+1"),
+            (SyntaxKind::Semicolon, ";"),
+            (SyntaxKind::Jump, "JMP"),
+        ]
+    );
+    assert_eq!(tree.nodes[4].text, "// x");
+}
+
+#[test]
+fn format() {
+    let code = "\n\n// multiplies\n   @R2\nM=0   // product\n(LOOP) @i\n\tDM=M+D // add\n\n\n\n   // next\n  A+D;JGT\n(END)\n@END\n0 ; JMP   \n\n";
+    assert_eq!(
+        format_asm(code).unwrap(),
+        "    // multiplies\n    @R2\n    M=0    // product\n(LOOP)\n    @i\n    MD=D+M // add\n\n    // next\n    D+A;JGT\n(END)\n    @END\n    0;JMP\n"
+    );
+
+    let aligned = "@R0\nD=M // first\n@SCREEN // screen\nAMD=M-1 // long\n";
+    assert_eq!(
+        format_asm(aligned).unwrap(),
+        "    @R0\n    D=M     // first\n    @SCREEN // screen\n    AMD=M-1 // long\n"
+    );
+
+    assert_eq!(format_asm("").unwrap(), "");
+}
+
+#[test]
+fn format_keeps_program() {
+    for file in FILES {
+        let code = read_to_string(file).unwrap();
+        let formatted = format_asm(&code).unwrap();
+        assert_eq!(parse(&formatted), parse(&code), "{}", file);
+        assert_eq!(format_asm(&formatted).unwrap(), formatted, "{}", file);
+    }
+}
+
+#[test]
+fn format_errors() {
+    assert_eq!(
+        format_asm("@R0\n  D=Q\n"),
+        Err("2:3: cannot format `D=Q`".to_string())
+    );
+    assert!(format_asm("M=M+1;\n").is_err());
+    assert!(format_asm("#\n").is_err());
+}
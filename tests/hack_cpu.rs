@@ -0,0 +1,549 @@
+use n2t_lib::cpu::{
+    alu, asm2ml, parse, parse_with_labels, str2ml, AccessKind, Breakpoint, Cmp, Comp, Condition,
+    CpuError, HackCpu, Key, KeyEvent, Keyboard, MemoryAccess, Register, RunOutcome, Watchpoint,
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+use std::fs::read_to_string;
+
+fn load(path: &str) -> HackCpu {
+    HackCpu::new(parse(&read_to_string(path).unwrap()).unwrap())
+}
+
+#[test]
+fn run_halts() {
+    let mut cpu = load("tests/projects/06/max/Max.asm");
+    assert_eq!(cpu.run(1000), RunOutcome::Halted { cycles: 13 });
+    assert!(cpu.is_halted());
+
+    let mut cpu = load("tests/projects/06/rect/Rect.asm");
+    assert_eq!(cpu.run(1000), RunOutcome::Halted { cycles: 5 });
+}
+
+#[test]
+fn run_budget() {
+    let code = r"
+    (LOOP)
+        @LOOP
+        D=D+1;JMP
+    ";
+    let mut cpu = HackCpu::new(parse(code).unwrap());
+    assert_eq!(cpu.run(100), RunOutcome::BudgetExhausted { cycles: 100 });
+    assert!(!cpu.is_halted());
+}
+
+#[test]
+fn run_out_of_rom() {
+    let code = r"
+        @2
+        D=A
+        @3
+    ";
+    let mut cpu = HackCpu::new(parse(code).unwrap());
+    assert_eq!(cpu.run(100), RunOutcome::OutOfRom { pc: 3, cycles: 3 });
+}
+
+#[test]
+fn run_until() {
+    let mut cpu = load("tests/projects/06/max/Max.asm");
+    let mut steps = 0;
+    let outcome = cpu.run_until(|_| {
+        steps += 1;
+        steps > 5
+    });
+    assert_eq!(outcome, RunOutcome::Stopped { cycles: 5 });
+    assert_eq!(cpu.run(1000), RunOutcome::Halted { cycles: 8 });
+}
+
+const MULT: &str = r"
+    @R2
+    M=0
+(LOOP)
+    @R1
+    D=M
+    @END
+    D;JLE
+    @R0
+    D=M
+    @R2
+    M=D+M
+    @R1
+    M=M-1
+    @LOOP
+    0;JMP
+(END)
+    @END
+    0;JMP
+";
+
+#[test]
+fn mult() {
+    let mut cpu = HackCpu::new(parse(MULT).unwrap());
+    for (r0, r1) in [(0, 0), (1, 0), (0, 2), (3, 1), (2, 4), (6, 7)] {
+        cpu.reset();
+        cpu.set_ram_range(0, &[r0, r1, -1]);
+        assert!(matches!(cpu.run(1000), RunOutcome::Halted { .. }));
+        assert_eq!(cpu.ram(2), r0 * r1);
+    }
+}
+
+#[test]
+fn max() {
+    let mut cpu = load("tests/projects/06/max/Max.asm");
+    for (r0, r1) in [(3, 5), (23456, 12345), (-1, -4)] {
+        cpu.reset();
+        cpu.set_ram(0, r0);
+        cpu.set_ram(1, r1);
+        assert!(matches!(cpu.run(1000), RunOutcome::Halted { .. }));
+        assert_eq!(cpu.ram_range(0..3), vec![r0, r1, r0.max(r1)]);
+    }
+}
+
+#[test]
+fn machine_state() {
+    let hack = read_to_string("tests/projects/06/add/Add.hack").unwrap();
+    let mut cpu = HackCpu::from_ml(str2ml(&hack).unwrap()).unwrap();
+    assert_eq!(cpu.run(100), RunOutcome::OutOfRom { pc: 6, cycles: 6 });
+    assert_eq!((cpu.a_reg(), cpu.d_reg(), cpu.pc()), (0, 5, 6));
+    assert_eq!(cpu.ram(0), 5);
+
+    cpu.load_rom(parse("D=D+1").unwrap());
+    assert_eq!(cpu.pc(), 0);
+    cpu.step().unwrap();
+    assert_eq!(cpu.d_reg(), 6);
+
+    cpu.set_a_reg(7);
+    cpu.set_d_reg(-3);
+    cpu.set_pc(0);
+    cpu.load_ml(str2ml("1110001100001000").unwrap()).unwrap();
+    cpu.step().unwrap();
+    assert_eq!(cpu.ram(7), -3);
+    assert_eq!(cpu.rom().len(), 1);
+}
+
+#[test]
+fn wrapping_alu() {
+    let code = r"
+        @32767
+        D=A
+        D=D+1
+        @R0
+        M=D
+        D=-D
+        @R1
+        M=D
+        @5
+        D=-A
+        @R2
+        M=D
+        @R3
+        M=D-1
+        M=M-D
+    ";
+    let mut cpu = HackCpu::new(parse(code).unwrap());
+    assert_eq!(cpu.run(100), RunOutcome::OutOfRom { pc: 15, cycles: 15 });
+    assert_eq!(cpu.ram_range(0..4), vec![-32768, -32768, -5, -1]);
+}
+
+#[test]
+fn addressing() {
+    let code = r"
+        @32767
+        D=!A
+        A=D
+        M=1
+        @100
+        AM=A+1
+        D=M
+    ";
+    let mut cpu = HackCpu::new(parse(code).unwrap());
+    cpu.run(100);
+    // A = -32768 addresses RAM[0]
+    assert_eq!(cpu.ram(0), 1);
+    // AM=A+1 writes to the old address
+    assert_eq!(cpu.ram(100), 101);
+    assert_eq!(cpu.ram(101), 0);
+    assert_eq!(cpu.d_reg(), 0);
+
+    assert_eq!(cpu.step(), Err(CpuError::PcOutOfRom { pc: 7 }));
+    assert_eq!(
+        CpuError::PcOutOfRom { pc: 7 }.to_string(),
+        "pc 7 is outside of rom"
+    );
+}
+
+/// pc loads the A of before the instruction, like M is addressed with it
+#[test]
+fn jump_uses_old_a() {
+    let mut cpu = HackCpu::new(parse("@10\nA=A+1;JMP").unwrap());
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert_eq!((cpu.pc(), cpu.a_reg()), (10, 11));
+
+    let code = r"
+        @R5
+        AM=M-1;JEQ
+    ";
+    let mut cpu = HackCpu::new(parse(code).unwrap());
+    cpu.set_ram(5, 1);
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert_eq!((cpu.pc(), cpu.a_reg(), cpu.ram(5)), (5, 0, 0));
+
+    // not taken
+    cpu.set_pc(0);
+    cpu.set_ram(5, 3);
+    cpu.run(2);
+    assert_eq!((cpu.pc(), cpu.a_reg(), cpu.ram(5)), (2, 2, 2));
+}
+
+/// a-instructions load 15 bits, like the word asm2ml writes for them
+#[test]
+fn a_instruction_width() {
+    let program = parse("@40000\nD=A").unwrap();
+    assert_eq!(asm2ml(program.clone())[0], 7232);
+    let mut cpu = HackCpu::new(program);
+    cpu.run(2);
+    assert_eq!(cpu.d_reg(), 7232);
+}
+
+#[test]
+fn alu_mnemonics() {
+    let values: [i16; 8] = [0, 1, -1, 17, -3, 12345, 32767, -32768];
+    for &d in values.iter() {
+        for &a in values.iter() {
+            let m = a.wrapping_mul(3);
+            let expected = [
+                (Comp::Zero, 0),
+                (Comp::One, 1),
+                (Comp::MinusOne, -1),
+                (Comp::D, d),
+                (Comp::A, a),
+                (Comp::NotD, !d),
+                (Comp::NotA, !a),
+                (Comp::MinusD, d.wrapping_neg()),
+                (Comp::MinusA, a.wrapping_neg()),
+                (Comp::DPulsOne, d.wrapping_add(1)),
+                (Comp::APulsOne, a.wrapping_add(1)),
+                (Comp::DMinusOne, d.wrapping_sub(1)),
+                (Comp::AMinusOne, a.wrapping_sub(1)),
+                (Comp::DPulsA, d.wrapping_add(a)),
+                (Comp::DMinusA, d.wrapping_sub(a)),
+                (Comp::AMinusD, a.wrapping_sub(d)),
+                (Comp::DAndA, d & a),
+                (Comp::DOrA, d | a),
+                (Comp::M, m),
+                (Comp::NotM, !m),
+                (Comp::MinusM, m.wrapping_neg()),
+                (Comp::MPlusOne, m.wrapping_add(1)),
+                (Comp::MMinusOne, m.wrapping_sub(1)),
+                (Comp::DPulsM, d.wrapping_add(m)),
+                (Comp::DMinusM, d.wrapping_sub(m)),
+                (Comp::MMinusD, m.wrapping_sub(d)),
+                (Comp::DAndM, d & m),
+                (Comp::DOrM, d | m),
+            ];
+            for (comp, val) in expected {
+                assert_eq!(comp.eval(d, a, m), val, "{:?} d={} a={}", comp, d, a);
+            }
+        }
+    }
+}
+
+#[test]
+fn alu_undocumented() {
+    // zx nx zy ny f no = 1 1 0 0 1 0 computes A-1, with no it is !(A-1) = -A
+    assert_eq!(alu(5, 9, true, true, false, false, true, false), 8);
+    assert_eq!(alu(5, 9, true, true, false, false, true, true), -9);
+
+    // D+1 with the a bit set still ignores y
+    let mut cpu = HackCpu::from_ml(str2ml("1111011111010000").unwrap()).unwrap();
+    cpu.set_d_reg(41);
+    cpu.step().unwrap();
+    assert_eq!(cpu.d_reg(), 42);
+
+    // no applied to D&M is D nand M
+    let mut cpu = HackCpu::from_ml(str2ml("1111000001010000").unwrap()).unwrap();
+    cpu.set_d_reg(0b1100);
+    cpu.set_a_reg(3);
+    cpu.set_ram(3, 0b1010);
+    cpu.step().unwrap();
+    assert_eq!(cpu.d_reg(), !(0b1100 & 0b1010));
+}
+
+#[test]
+fn screen_rect() {
+    let mut cpu = load("tests/projects/06/rect/Rect.asm");
+    cpu.set_ram(0, 4);
+    assert!(matches!(cpu.run(1000), RunOutcome::Halted { .. }));
+
+    let screen = cpu.screen();
+    for y in 0..SCREEN_HEIGHT {
+        for x in 0..SCREEN_WIDTH {
+            assert_eq!(screen.pixel(x, y), x < 16 && y < 4, "pixel ({}, {})", x, y);
+        }
+    }
+    assert_eq!(screen.black_pixels(), 16 * 4);
+
+    let pbm = screen.to_pbm();
+    let header = b"P4\n512 256\n";
+    assert_eq!(&pbm[..header.len()], header);
+    assert_eq!(pbm.len(), header.len() + 512 * 256 / 8);
+    assert_eq!(&pbm[header.len()..header.len() + 3], &[0xff, 0xff, 0]);
+
+    let png = screen.to_png();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    assert_eq!(&png[png.len() - 12..png.len() - 4], b"\0\0\0\0IEND");
+
+    let path = std::env::temp_dir().join("n2t_lib_screen_rect.pbm");
+    screen.save_pbm(&path).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), pbm);
+}
+
+#[test]
+fn screen_pixel_order() {
+    let mut cpu = HackCpu::new(Vec::new());
+    // bit 0 is the leftmost pixel of a word
+    cpu.set_ram(16384 + 32 + 1, 0b101);
+    let screen = cpu.screen();
+    assert!(screen.pixel(16, 1));
+    assert!(!screen.pixel(17, 1));
+    assert!(screen.pixel(18, 1));
+    assert_eq!(screen.black_pixels(), 2);
+}
+
+const FILL: &str = r"
+(LOOP)
+    @color
+    M=0
+    @KBD
+    D=M
+    @FILL
+    D;JEQ
+    @color
+    M=-1
+(FILL)
+    @SCREEN
+    D=A
+    @addr
+    M=D
+(NEXT)
+    @color
+    D=M
+    @addr
+    A=M
+    M=D
+    @addr
+    MD=M+1
+    @KBD
+    D=D-A
+    @NEXT
+    D;JLT
+    @LOOP
+    0;JMP
+";
+
+#[test]
+fn keyboard_fill() {
+    let mut keyboard = Keyboard::new();
+    keyboard.tap(10, Key::Char('A'), 300_000);
+    let mut cpu = HackCpu::new(parse(FILL).unwrap());
+    cpu.set_keyboard(keyboard);
+
+    cpu.run(10);
+    assert_eq!(cpu.ram(24576), 0);
+    // a full pass over the screen takes about 100_000 cycles
+    cpu.run(250_000);
+    assert_eq!(cpu.ram(24576), 65);
+    assert_eq!(cpu.screen().black_pixels(), 512 * 256);
+
+    cpu.run(300_000);
+    assert_eq!(cpu.ram(24576), 0);
+    assert_eq!(cpu.screen().black_pixels(), 0);
+    assert_eq!(cpu.cycles(), 550_010);
+}
+
+#[test]
+fn keyboard_timeline() {
+    let mut keyboard = Keyboard::new();
+    keyboard
+        .type_str(4, "hi\n", 2)
+        .press(20, Key::Up)
+        .press(22, Key::F(12))
+        .release(24);
+    assert_eq!(keyboard.events()[0], (4, KeyEvent::Press(Key::Char('h'))));
+
+    let mut cpu = HackCpu::new(parse("(LOOP)\n@LOOP\nD=D+1;JMP").unwrap());
+    cpu.set_keyboard(keyboard);
+    let mut codes = Vec::new();
+    for _ in 0..26 {
+        cpu.step().unwrap();
+        codes.push(cpu.ram(24576));
+    }
+    assert_eq!(
+        codes,
+        vec![
+            0, 0, 0, 0, 104, 104, 0, 0, 105, 105, 0, 0, 128, 128, 0, 0, 0, 0, 0, 0, 131, 131, 152,
+            152, 0, 0
+        ]
+    );
+
+    assert_eq!(Key::from_code(131), Some(Key::Up));
+    assert_eq!(Key::from_code(Key::F(3).code()), Some(Key::F(3)));
+    assert_eq!(Key::from_code(0), None);
+
+    // keys outside the hack character set read as no key
+    assert_eq!(Key::Char('~').code(), 126);
+    assert_eq!(Key::Char('\t').code(), 0);
+    assert_eq!(Key::Char('é').code(), 0);
+    assert_eq!(Key::Char('\u{1f600}').code(), 0);
+    assert_eq!(Key::F(0).code(), 0);
+    assert_eq!(Key::F(13).code(), 0);
+    assert_eq!(Key::F(255).code(), 0);
+}
+
+#[test]
+fn breakpoints() {
+    let (rom, labels) = parse_with_labels(MULT).unwrap();
+    assert_eq!(labels.get("LOOP"), Some(&2));
+    assert_eq!(labels.get("R2"), None);
+
+    let mut cpu = HackCpu::new(rom);
+    cpu.set_labels(labels);
+    cpu.set_ram_range(0, &[3, 2]);
+    assert!(cpu
+        .add_breakpoint(Breakpoint::Label("NOPE".to_string()))
+        .is_err());
+    let loop_id = cpu
+        .add_breakpoint(Breakpoint::Label("LOOP".to_string()))
+        .unwrap();
+
+    // every iteration stops at LOOP, resuming steps over the breakpoint
+    assert_eq!(
+        cpu.run(1000),
+        RunOutcome::Breakpoint {
+            id: loop_id,
+            cycles: 2
+        }
+    );
+    assert_eq!(cpu.pc(), 2);
+    assert_eq!(
+        cpu.run(1000),
+        RunOutcome::Breakpoint {
+            id: loop_id,
+            cycles: 12
+        }
+    );
+    assert_eq!(cpu.ram(2), 3);
+    assert!(cpu.remove_breakpoint(loop_id));
+    assert!(!cpu.remove_breakpoint(loop_id));
+
+    // R1 reaches 0 before the last check of the loop
+    let cond = Condition::new(Register::Ram(1), Cmp::Eq, 0);
+    let cond_id = cpu
+        .add_breakpoint(Breakpoint::ConditionalAddress(2, cond.clone()))
+        .unwrap();
+    assert_eq!(
+        cpu.run(1000),
+        RunOutcome::Breakpoint {
+            id: cond_id,
+            cycles: 12
+        }
+    );
+    assert!(cond.holds(&cpu));
+    assert_eq!(cpu.ram(2), 6);
+
+    cpu.clear_breakpoints();
+    let d_id = cpu
+        .add_breakpoint(Breakpoint::Condition(Condition::new(
+            Register::D,
+            Cmp::Le,
+            0,
+        )))
+        .unwrap();
+    assert_eq!(
+        cpu.run(1000),
+        RunOutcome::Breakpoint {
+            id: d_id,
+            cycles: 2
+        }
+    );
+    assert_eq!(cpu.pc(), 4);
+}
+
+#[test]
+fn watchpoints() {
+    let mut cpu = HackCpu::new(parse(MULT).unwrap());
+    cpu.set_ram_range(0, &[3, 2, -1]);
+
+    let write_id = cpu.add_watchpoint(Watchpoint::Write(2..3));
+    assert_eq!(
+        cpu.run(1000),
+        RunOutcome::Watchpoint {
+            id: write_id,
+            access: MemoryAccess {
+                addr: 2,
+                kind: AccessKind::Write,
+                old: -1,
+                new: 0
+            },
+            cycles: 2
+        }
+    );
+    assert_eq!(
+        cpu.run(1000),
+        RunOutcome::Watchpoint {
+            id: write_id,
+            access: MemoryAccess {
+                addr: 2,
+                kind: AccessKind::Write,
+                old: 0,
+                new: 3
+            },
+            cycles: 8
+        }
+    );
+    assert_eq!(cpu.last_read().map(|r| r.addr), Some(2));
+
+    cpu.clear_breakpoints();
+    let read_id = cpu.add_watchpoint(Watchpoint::Read(0..1));
+    match cpu.run(1000) {
+        RunOutcome::Watchpoint { id, access, .. } => {
+            assert_eq!(id, read_id);
+            assert_eq!(
+                (access.addr, access.kind, access.new),
+                (0, AccessKind::Read, 3)
+            );
+        }
+        outcome => panic!("unexpected {:?}", outcome),
+    }
+    cpu.clear_breakpoints();
+    cpu.add_watchpoint(Watchpoint::Access(16384..24576));
+    assert!(matches!(cpu.run(1000), RunOutcome::Halted { .. }));
+    assert_eq!(cpu.ram(2), 6);
+}
+
+#[test]
+fn fast_path_matches_step() {
+    let ml = str2ml(&read_to_string("tests/projects/06/pong/Pong.hack").unwrap()).unwrap();
+    let mut keyboard = Keyboard::new();
+    keyboard
+        .tap(200_000, Key::Left, 50_000)
+        .tap(400_000, Key::Right, 50_000);
+
+    let mut fast = HackCpu::from_ml(ml.clone()).unwrap();
+    fast.set_keyboard(keyboard.clone());
+    let mut slow = HackCpu::from_ml(ml).unwrap();
+    slow.set_keyboard(keyboard);
+    // a trace forces run onto the instrumented path
+    slow.enable_trace(1);
+
+    for _ in 0..6 {
+        assert_eq!(fast.run(100_000), slow.run(100_000));
+        assert_eq!(
+            (fast.pc(), fast.a_reg(), fast.d_reg(), fast.cycles()),
+            (slow.pc(), slow.a_reg(), slow.d_reg(), slow.cycles())
+        );
+        assert_eq!(fast.last_write(), slow.last_write());
+        assert_eq!(fast.ram_range(0..0x8000), slow.ram_range(0..0x8000));
+    }
+}
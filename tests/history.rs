@@ -0,0 +1,122 @@
+use n2t_lib::cpu::{parse, HackCpu, Key, Keyboard, RunOutcome};
+
+const SUM: &str = r"
+    @idx
+    M=1
+    @sum
+    M=0
+(LOOP)
+    @idx
+    D=M
+    @sum
+    M=D+M
+    @idx
+    M=M+1
+    @LOOP
+    0;JMP
+";
+
+#[test]
+fn step_back() {
+    let mut cpu = HackCpu::new(parse(SUM).unwrap());
+    assert!(!cpu.step_back());
+
+    cpu.enable_history(1000);
+    cpu.run(100);
+    let state = (cpu.pc(), cpu.a_reg(), cpu.d_reg(), cpu.ram_range(16..18));
+
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert!(cpu.step_back());
+    assert!(cpu.step_back());
+    assert!(cpu.step_back());
+    assert_eq!(cpu.cycles(), 100);
+    assert_eq!(
+        (cpu.pc(), cpu.a_reg(), cpu.d_reg(), cpu.ram_range(16..18)),
+        state
+    );
+
+    while cpu.step_back() {}
+    assert_eq!(cpu.cycles(), 0);
+    assert_eq!((cpu.pc(), cpu.a_reg(), cpu.d_reg()), (0, 0, 0));
+    assert_eq!(cpu.ram_range(16..18), vec![0, 0]);
+}
+
+#[test]
+fn snapshots() {
+    let mut cpu = HackCpu::new(parse(SUM).unwrap());
+    cpu.enable_history(500);
+    cpu.run(50);
+    let snapshot = cpu.snapshot();
+    let ram = cpu.ram_range(16..18);
+
+    cpu.run(400);
+    assert_ne!(cpu.ram_range(16..18), ram);
+    cpu.rewind(&snapshot).unwrap();
+    assert_eq!(cpu.cycles(), snapshot.cycles());
+    assert_eq!(cpu.pc(), snapshot.pc());
+    assert_eq!(cpu.ram_range(16..18), ram);
+
+    // the journal only holds 500 instructions
+    cpu.run(600);
+    assert_eq!(cpu.history_len(), 500);
+    assert!(cpu.rewind(&snapshot).is_err());
+
+    let future = cpu.snapshot();
+    cpu.step_back();
+    assert!(cpu.rewind(&future).is_err());
+}
+
+/// registers changed with the setters are restored, ram set from outside is kept
+#[test]
+fn rewind_after_setters() {
+    let mut cpu = HackCpu::new(parse(SUM).unwrap());
+    cpu.enable_history(100);
+    cpu.run(10);
+    let snapshot = cpu.snapshot();
+    let registers = (cpu.pc(), cpu.a_reg(), cpu.d_reg());
+
+    cpu.set_d_reg(99);
+    cpu.set_a_reg(5);
+    cpu.step().unwrap();
+    cpu.set_ram(5, 7);
+    cpu.reset();
+    cpu.step().unwrap();
+    cpu.rewind(&snapshot).unwrap();
+    assert_eq!((cpu.pc(), cpu.a_reg(), cpu.d_reg()), registers);
+    assert_eq!(cpu.cycles(), 10);
+    assert_eq!(cpu.ram(5), 7);
+}
+
+#[test]
+fn keyboard_is_rewound() {
+    let code = r"
+    (LOOP)
+        @KBD
+        D=M
+        @LOOP
+        0;JMP
+    ";
+    let mut keyboard = Keyboard::new();
+    keyboard.tap(5, Key::Char('x'), 5);
+    let mut cpu = HackCpu::new(parse(code).unwrap());
+    cpu.set_keyboard(keyboard);
+    cpu.enable_history(100);
+
+    assert_eq!(cpu.run(8), RunOutcome::BudgetExhausted { cycles: 8 });
+    assert_eq!(cpu.ram(24576), 120);
+    let snapshot = cpu.snapshot();
+    cpu.run(8);
+    assert_eq!(cpu.ram(24576), 0);
+
+    cpu.rewind(&snapshot).unwrap();
+    assert_eq!(cpu.ram(24576), 120);
+    while cpu.step_back() {}
+    assert_eq!(cpu.ram(24576), 0);
+
+    // replaying gives the same key presses
+    cpu.run(8);
+    assert_eq!(cpu.ram(24576), 120);
+    assert_eq!(cpu.d_reg(), 120);
+}
@@ -0,0 +1,113 @@
+use n2t_lib::cpu::{
+    bin2ml, ihex2ml, logisim2ml, ml2bin, ml2ihex, ml2logisim, ml2readmemb, ml2str, readmemb2ml,
+    str2ml,
+};
+use std::fs::read_to_string;
+
+fn pong() -> Vec<u16> {
+    str2ml(&read_to_string("tests/projects/06/pong/Pong.hack").unwrap()).unwrap()
+}
+
+#[test]
+fn round_trips() {
+    for ml in [
+        vec![],
+        vec![0x8000, 0xffff, 0, 21, 21, 21, 21, 21, 7],
+        pong(),
+    ] {
+        assert_eq!(str2ml(&ml2str(&ml)).unwrap(), ml);
+        assert_eq!(bin2ml(&ml2bin(&ml)).unwrap(), ml);
+        assert_eq!(ihex2ml(&ml2ihex(&ml)).unwrap(), ml);
+        assert_eq!(readmemb2ml(&ml2readmemb(&ml)).unwrap(), ml);
+        assert_eq!(logisim2ml(&ml2logisim(&ml)).unwrap(), ml);
+    }
+}
+
+#[test]
+fn hack() {
+    let hack = read_to_string("tests/projects/06/add/Add.hack").unwrap();
+    assert_eq!(ml2str(&str2ml(&hack).unwrap()), hack.replace("\r\n", "\n"));
+}
+
+#[test]
+fn binary() {
+    assert_eq!(ml2bin(&[0x1234, 0xec10]), vec![0x12, 0x34, 0xec, 0x10]);
+    assert!(bin2ml(&[1, 2, 3]).is_err());
+}
+
+#[test]
+fn intel_hex() {
+    let ml: Vec<u16> = (0..9).collect();
+    assert_eq!(
+        ml2ihex(&ml),
+        ":1000000000000001000200030004000500060007D4\n\
+         :020010000008E6\n\
+         :00000001FF\n"
+    );
+
+    // an extended segment address, a gap and no end of file record
+    let hex = ":020000021000EC\n:0400020012345678E6\n";
+    let mut expected = vec![0; 0x10000 / 2 + 1];
+    expected.extend([0x1234, 0x5678]);
+    assert_eq!(ihex2ml(hex).unwrap(), expected);
+
+    assert_eq!(
+        ihex2ml(":020010000008E7").unwrap_err(),
+        "wrong checksum in line 1"
+    );
+    assert_eq!(
+        ihex2ml("\n020010000008E6").unwrap_err(),
+        "a record has to start with `:` in line 2"
+    );
+    assert_eq!(
+        ihex2ml(":030010000008E6").unwrap_err(),
+        "the length of the record does not match its byte count in line 1"
+    );
+    assert_eq!(
+        ihex2ml(":0é000000é0").unwrap_err(),
+        "a record has to consist of hex digits in line 1"
+    );
+    // data above 128 KiB is refused instead of allocated
+    assert_eq!(
+        ihex2ml(":02000004FFFFFC\n:0100000001FE\n").unwrap_err(),
+        "the image reaches 2147450881 words but at most 65536 are supported in line 2"
+    );
+}
+
+#[test]
+fn readmemb() {
+    let mem = "// Add.hack\n\
+               0000_0000_0000_0010 1110110000010000 /* two\n\
+               words */ @4\n\
+               1111111111111111\n";
+    assert_eq!(readmemb2ml(mem).unwrap(), vec![2, 0xec10, 0, 0, 0xffff]);
+    assert_eq!(
+        readmemb2ml("\n\n/* 2 */ 0102").unwrap_err(),
+        "`0102` is not a 16 bit binary word in line 3"
+    );
+    assert_eq!(
+        readmemb2ml("@FFFFFFFF 0").unwrap_err(),
+        "the image reaches 4294967296 words but at most 65536 are supported in line 1"
+    );
+    assert_eq!(readmemb2ml("@FFFF 1").unwrap().len(), 0x10000);
+}
+
+#[test]
+fn logisim() {
+    let ml = [1, 2, 2, 2, 2, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+    assert_eq!(ml2logisim(&ml), "v2.0 raw\n1 5*2 3 4 5 6 7 8\n9 a\n");
+    assert_eq!(
+        logisim2ml("v2.0 raw\n# comment\n3*ec10 ffff # end\n").unwrap(),
+        vec![0xec10, 0xec10, 0xec10, 0xffff]
+    );
+    assert!(logisim2ml("1 2 3").is_err());
+    assert_eq!(
+        logisim2ml("v2.0 raw\n12345").unwrap_err(),
+        "`12345` is not a 16 bit hex word in line 2"
+    );
+    assert_eq!(
+        logisim2ml("v2.0 raw\n1\n4000000000*0").unwrap_err(),
+        "the image reaches 4000000001 words but at most 65536 are supported in line 3"
+    );
+    assert_eq!(logisim2ml("v2.0 raw\n65536*0").unwrap().len(), 0x10000);
+}
@@ -0,0 +1,57 @@
+use n2t_lib::cpu::{listing, parse_program, AsmProgram};
+use std::collections::HashMap;
+use std::fs::read_to_string;
+
+#[test]
+fn small() {
+    let code = "@i\nM=1\n(LOOP) @i\n  D=M;JGT // loop\n(END)";
+    let program = parse_program(code).unwrap();
+    assert_eq!(
+        listing(&program, code),
+        "  rom  binary            hex    line  label  source
+    0  0000000000010000  0010      1         @i
+    1  1110111111001000  EFC8      2         M=1
+    2  0000000000010000  0010      3  LOOP   (LOOP) @i
+    3  1111110000010001  FC11      4         D=M;JGT // loop
+    4                                 END
+
+labels
+  LOOP      2
+  END       4
+
+variables
+  i     16
+"
+    );
+}
+
+/// every rom address of Max.asm has a row with its word from Max.hack
+#[test]
+fn max() {
+    let code = read_to_string("tests/projects/06/max/Max.asm").unwrap();
+    let hack = read_to_string("tests/projects/06/max/Max.hack").unwrap();
+    let program = parse_program(&code).unwrap();
+    let text = listing(&program, &code);
+
+    let rows: Vec<&str> = text.lines().skip(1).take_while(|l| !l.is_empty()).collect();
+    assert_eq!(rows.len(), 16);
+    for (row, word) in rows.iter().zip(hack.lines()) {
+        assert_eq!(&row[7..23], word.trim());
+    }
+    assert!(rows[10].contains("  19  OUTPUT_FIRST   @R0"));
+    assert!(rows[13].ends_with("M=D              // M[2] = D (greatest number)"));
+    assert!(text.contains("\nlabels\n  OUTPUT_FIRST      10\n"));
+}
+
+#[test]
+fn without_source_map() {
+    let program = AsmProgram {
+        instructions: parse_program("@7\nD=A").unwrap().instructions,
+        labels: HashMap::new(),
+        variables: HashMap::new(),
+        relocations: Vec::new(),
+        source_map: Vec::new(),
+    };
+    let text = listing(&program, "");
+    assert!(text.contains("\n    1  1110110000010000  EC10      -         D=A\n"));
+}
@@ -0,0 +1,130 @@
+use n2t_lib::cpu::{
+    assemble_for, parse, parse_program, parse_program_for, HackCpu, Key, Keyboard, MachineProfile,
+    RunOutcome,
+};
+use n2t_lib::vm::{vm2asm, vm2asm_for, Segment, VMInstruction};
+use std::fs::read_to_string;
+
+/// a profile with the screen at 8192, the keyboard right after it and variables from 32
+fn small() -> MachineProfile {
+    MachineProfile {
+        name: String::from("small"),
+        ram_size: 0x8000,
+        rom_size: 0x100,
+        screen: 0x2000,
+        keyboard: 0x2000 + 8192,
+        statics: 32,
+        temp: 20,
+    }
+}
+
+#[test]
+fn validate() {
+    assert_eq!(MachineProfile::default(), MachineProfile::hack());
+    MachineProfile::hack().validate().unwrap();
+    MachineProfile::extended().validate().unwrap();
+    small().validate().unwrap();
+
+    let profile = MachineProfile {
+        ram_size: 0x9000,
+        ..MachineProfile::hack()
+    };
+    assert_eq!(
+        profile.validate().unwrap_err(),
+        "the ram of hack has 36864 words but needs a power of two up to 65536"
+    );
+    let profile = MachineProfile {
+        keyboard: 20000,
+        ..MachineProfile::hack()
+    };
+    assert_eq!(
+        profile.validate().unwrap_err(),
+        "the keyboard of hack at 20000 is inside the screen at 16384"
+    );
+    let profile = MachineProfile {
+        screen: 0x7000,
+        ..MachineProfile::hack()
+    };
+    assert!(profile.validate().is_err());
+    assert!(HackCpu::with_machine(Vec::new(), profile).is_err());
+}
+
+/// writes 1 to the address 0x9000, which is 0x1000 on a 32K machine
+const HIGH_WRITE: &str = "
+    @18432
+    D=A
+    A=D+A
+    M=1
+(END)
+    @END
+    0;JMP
+";
+
+#[test]
+fn address_width() {
+    let mut cpu = HackCpu::new(parse(HIGH_WRITE).unwrap());
+    cpu.run(100);
+    assert_eq!(cpu.ram(0x1000), 1);
+
+    let program = parse(HIGH_WRITE).unwrap();
+    let mut cpu = HackCpu::with_machine(program, MachineProfile::extended()).unwrap();
+    assert!(matches!(cpu.run(100), RunOutcome::Halted { .. }));
+    assert_eq!(cpu.ram(0x9000), 1);
+    assert_eq!(cpu.ram(0x1000), 0);
+    assert_eq!(cpu.machine().ram_size, 0x10000);
+}
+
+#[test]
+fn assembler() {
+    let code = "@SCREEN\nD=A\n@KBD\nD=A\n@x\nM=D\n@x\nM=0";
+    let program = parse_program_for(code, &small()).unwrap();
+    assert_eq!(program.instructions[0], parse("@8192").unwrap()[0]);
+    assert_eq!(program.instructions[2], parse("@16384").unwrap()[0]);
+    assert_eq!(program.variables["x"], 32);
+    assert_eq!(parse_program(code).unwrap().variables["x"], 16);
+
+    let pong = read_to_string("tests/projects/06/pong/Pong.asm").unwrap();
+    let assembly = assemble_for(&pong, &small());
+    assert!(assembly.program.is_some());
+    assert!(assembly.warnings().any(|w| w
+        .message
+        .ends_with("instructions but the rom only holds 256")));
+
+    // the extended rom has labels an a-instruction cannot load
+    let far = format!("{}(FAR)\n@FAR\n0;JMP", "D=0\n".repeat(0x8000));
+    let assembly = assemble_for(&far, &MachineProfile::extended());
+    assert!(assembly.program.is_none());
+    assert_eq!(
+        assembly.errors().next().unwrap().message,
+        "FAR is at 32768 but a-instructions only reach 32767"
+    );
+}
+
+#[test]
+fn screen_and_keyboard() {
+    let code = "
+        @KBD
+        D=M
+        @SCREEN
+        M=D
+    (END)
+        @END
+        0;JMP
+    ";
+    let program = parse_program_for(code, &small()).unwrap();
+    let mut cpu = HackCpu::with_machine(program.instructions, small()).unwrap();
+    let mut keyboard = Keyboard::new();
+    keyboard.press(0, Key::Char('A'));
+    cpu.set_keyboard(keyboard);
+    cpu.run(100);
+    assert_eq!(cpu.ram(0x2000), 65);
+    assert_eq!(cpu.screen().words()[0], 65);
+    assert_eq!(cpu.ram(16384), 65);
+}
+
+#[test]
+fn vm() {
+    let code = vec![VMInstruction::Push(Segment::Static, 2)];
+    assert_eq!(vm2asm(code.clone())[0], parse("@18").unwrap()[0]);
+    assert_eq!(vm2asm_for(code, &small())[0], parse("@34").unwrap()[0]);
+}
@@ -0,0 +1,192 @@
+use n2t_lib::cpu::{assemble_macros, parse, HackCpu, Origin, Preprocessor};
+
+fn run(code: &str, preprocessor: &Preprocessor, cycles: usize) -> HackCpu {
+    let (program, _) = assemble_macros(code, preprocessor).unwrap();
+    let mut cpu = HackCpu::new(program.instructions);
+    cpu.set_ram(0, 256);
+    cpu.run(cycles);
+    cpu
+}
+
+#[test]
+fn equ() {
+    let code = r"
+.equ WIDTH 32
+.equ MASK 0x00ff
+.equ COPY WIDTH
+    @WIDTH
+    D=A
+    @MASK // a comment
+    D=D+A
+    @COPY
+    D=D+A
+    @R5
+    M=D
+";
+    let expansion = Preprocessor::new().expand(code).unwrap();
+    assert!(expansion.code.contains("@255 // a comment"));
+    let cpu = run(code, &Preprocessor::new(), 8);
+    assert_eq!(cpu.ram(5), 32 + 255 + 32);
+}
+
+#[test]
+fn pseudo_instructions() {
+    let code = r"
+    LOADD 0
+    PUSHD
+    LOADD -1
+    PUSHD
+    LOADD 1000
+    PUSHD
+    LOADD -32768
+    PUSHD
+    LOADD -7
+    PUSHD
+    LOADD 0xFFFF
+    PUSHD
+    LOADD 0x8000
+    PUSHD
+    POPD
+    POPD
+    POPD
+    @R13
+    M=D
+    GOTO END
+    LOADD 99
+    PUSHD
+(END)
+    HALT
+";
+    let cpu = run(code, &Preprocessor::new(), 200);
+    assert_eq!(cpu.ram_range(256..260), vec![0, -1, 1000, -32768]);
+    assert_eq!(cpu.ram(0), 260);
+    assert_eq!(cpu.ram(13), -7);
+    assert_eq!(cpu.ram_range(261..263), vec![-1, -32768]);
+    assert!(cpu.is_halted());
+}
+
+#[test]
+fn macros() {
+    let code = r"
+.macro ADD_TO addr, value
+    LOADD %value
+    @%addr
+    M=D+M
+.endm
+.macro REPEAT count, addr
+    LOADD %count
+    @%addr
+    M=D
+(LOOP%%)
+    ADD_TO R1, 3
+    @%addr
+    MD=M-1
+    @LOOP%%
+    D;JGT
+.endm
+    REPEAT 4, R10
+    REPEAT 2, R11
+    HALT
+";
+    let cpu = run(code, &Preprocessor::new(), 500);
+    assert_eq!(cpu.ram(1), 3 * 6);
+    assert!(cpu.is_halted());
+}
+
+#[test]
+fn includes() {
+    let mut preprocessor = Preprocessor::new();
+    preprocessor.file("consts.asm", ".equ ANSWER 42\n").file(
+        "lib.asm",
+        ".include \"consts.asm\"\n.macro STORE addr\n    @%addr\n    M=D\n.endm\n",
+    );
+    let code = ".include \"lib.asm\"\n    LOADD ANSWER\n    STORE R3\n";
+    let cpu = run(code, &preprocessor, 10);
+    assert_eq!(cpu.ram(3), 42);
+
+    let dir = std::env::temp_dir().join("n2t_macros_include");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("set.asm"), "@R4\nM=1\n").unwrap();
+    let mut preprocessor = Preprocessor::new();
+    preprocessor.base_dir(&dir);
+    let cpu = run(".include \"set.asm\"\n", &preprocessor, 2);
+    assert_eq!(cpu.ram(4), 1);
+}
+
+#[test]
+fn plain_assembly_is_unchanged() {
+    let code = "(LOOP)\n    @i\n    M=M+1 // count\n    @LOOP\n    0;JMP\n";
+    let (program, expansion) = assemble_macros(code, &Preprocessor::new()).unwrap();
+    assert_eq!(expansion.code, code);
+    assert_eq!(program.instructions, parse(code).unwrap());
+}
+
+#[test]
+fn origins() {
+    let mut preprocessor = Preprocessor::new();
+    preprocessor.file("lib.asm", "// lib\n.macro SET\n    M=1\n.endm\n");
+    let code = ".include \"lib.asm\"\n@R1\nSET\n";
+    let expansion = preprocessor.expand(code).unwrap();
+    assert_eq!(expansion.code, "// lib\n@R1\n    M=1\n");
+    let origin = |file: &str, line| Origin {
+        file: file.to_string(),
+        line,
+    };
+    assert_eq!(
+        expansion.origins,
+        vec![origin("lib.asm", 1), origin("", 2), origin("lib.asm", 3)]
+    );
+    assert_eq!(expansion.origin(3).unwrap().to_string(), "lib.asm:3");
+}
+
+#[test]
+fn errors() {
+    let mut preprocessor = Preprocessor::new();
+    preprocessor
+        .file("a.asm", ".include \"b.asm\"\n")
+        .file("b.asm", "\n.include \"a.asm\"\n")
+        .file("bad.asm", "@R1\nM=X\n");
+    let cases = [
+        (".equ X", "1: expected `.equ NAME value`"),
+        (".equ X 1\n.equ X 2", "2: the constant X is already defined"),
+        (".equ X 70000", "1: 70000 does not fit into 16 bits"),
+        (
+            ".equ X -1\n@X",
+            "2: the constant X is negative and cannot be loaded into A, use LOADD",
+        ),
+        (".macro M\nD=1", "1: the macro M has no `.endm`"),
+        (
+            ".macro M\n.macro N\n.endm",
+            "2: macros cannot be defined inside a macro",
+        ),
+        (".endm", "1: `.endm` without `.macro`"),
+        (
+            ".macro M a\n.endm\nM",
+            "3: the macro M takes 1 arguments but got 0",
+        ),
+        (
+            ".macro M\nM\n.endm\nM",
+            "2: macros or includes are nested more than 64 levels deep",
+        ),
+        ("PUSHD D", "1: PUSHD takes 0 arguments but got 1"),
+        ("LOADD 40000", "1: 40000 does not fit into 16 bits"),
+        ("LOADD 0x10000", "1: 0x10000 does not fit into 16 bits"),
+        ("LOADD 12x", "1: `12x` is neither a number nor a constant"),
+        ("\n.include \"a.asm\"", "b.asm:2: a.asm includes itself"),
+        (
+            ".include \"bad.asm\"",
+            "bad.asm:2: expected a computation but got `X`",
+        ),
+    ];
+    for (code, error) in cases {
+        assert_eq!(
+            assemble_macros(code, &preprocessor).unwrap_err(),
+            error,
+            "{}",
+            code
+        );
+    }
+    assert!(assemble_macros(".include \"missing.asm\"", &preprocessor)
+        .unwrap_err()
+        .starts_with("1: cannot include missing.asm: "));
+}
@@ -0,0 +1,141 @@
+use n2t_lib::cpu::{parse_program, ConsoleOut, HackCpu, MemIssue, MemIssueKind, RunOutcome};
+use std::fs::read_to_string;
+
+/// Mult.asm that forgets to set R2 to 0, it passes as long as ram starts zeroed
+const MULT: &str = "\
+(LOOP)
+    @R1
+    D=M
+    @END
+    D;JLE
+    @R0
+    D=M
+    @R2
+    M=D+M
+    @R1
+    M=M-1
+    @LOOP
+    0;JMP
+(END)
+    @END
+    0;JMP
+";
+
+#[test]
+fn uninitialized_read() {
+    let program = parse_program(MULT).unwrap();
+    let mut cpu = HackCpu::from_program(&program);
+    cpu.enable_memcheck();
+    cpu.set_ram_range(0, &[6, 7]);
+    assert!(matches!(cpu.run(1000), RunOutcome::Halted { .. }));
+    assert_eq!(cpu.ram(2), 42);
+
+    let memcheck = cpu.memcheck().unwrap();
+    assert_eq!(
+        memcheck.issues(),
+        [MemIssue {
+            kind: MemIssueKind::UninitializedRead,
+            pc: 7,
+            addr: 2,
+            cycle: 7,
+            count: 1,
+        }]
+    );
+    assert!(memcheck.is_written(2));
+    assert!(!memcheck.is_written(3));
+    assert_eq!(
+        memcheck.report(&program),
+        "line 9, pc 7: read of 2 which was never written (at cycle 7)\n"
+    );
+
+    // with the fix the check is clean
+    let fixed = format!("@R2\nM=0\n{}", MULT);
+    let mut cpu = HackCpu::from_program(&parse_program(&fixed).unwrap());
+    cpu.enable_memcheck();
+    cpu.set_ram_range(0, &[6, 7]);
+    cpu.run(1000);
+    assert!(cpu.memcheck().unwrap().is_clean());
+}
+
+#[test]
+fn repeated_reads() {
+    let code = "
+        @i
+        D=M
+        @R0
+        M=D
+        @i
+        M=M+1
+        D=M
+        @10
+        D=D-A
+        @2
+        D;JLT
+    (END)
+        @END
+        0;JMP
+    ";
+    let program = parse_program(code).unwrap();
+    let mut cpu = HackCpu::from_program(&program);
+    cpu.enable_memcheck();
+    cpu.run(1000);
+    let memcheck = cpu.disable_memcheck().unwrap();
+    // M=M+1 reads i before it writes it, only the first time is a problem
+    assert_eq!(memcheck.issues().len(), 2);
+    assert_eq!(memcheck.issues()[1].pc, 5);
+    assert_eq!(memcheck.issues()[1].count, 1);
+    assert!(memcheck
+        .report(&program)
+        .starts_with("line 3, pc 1: read of 16 which was never written (at cycle 1)\n"));
+    assert!(cpu.memcheck().is_none());
+}
+
+#[test]
+fn keyboard_and_unmapped() {
+    let code = "
+        @KBD
+        D=M
+        M=D
+        @SCREEN
+        D=M
+        @24600
+        M=D
+        D=M
+        @24601
+        M=1
+    (END)
+        @END
+        0;JMP
+    ";
+    let program = parse_program(code).unwrap();
+    let mut cpu = HackCpu::from_program(&program);
+    cpu.map_device(24601, ConsoleOut::new()).unwrap();
+    cpu.enable_memcheck();
+    cpu.run(1000);
+    let kinds: Vec<(MemIssueKind, usize)> = cpu
+        .memcheck()
+        .unwrap()
+        .issues()
+        .iter()
+        .map(|issue| (issue.kind, issue.pc))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            (MemIssueKind::KeyboardWrite, 2),
+            (MemIssueKind::UnmappedWrite, 6),
+            (MemIssueKind::UnmappedRead, 7)
+        ]
+    );
+}
+
+#[test]
+fn rect() {
+    let program =
+        parse_program(&read_to_string("tests/projects/06/rect/Rect.asm").unwrap()).unwrap();
+    let mut cpu = HackCpu::from_program(&program);
+    cpu.enable_memcheck();
+    cpu.set_ram(0, 4);
+    cpu.run(1000);
+    assert!(cpu.memcheck().unwrap().is_clean());
+}
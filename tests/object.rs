@@ -0,0 +1,246 @@
+use n2t_lib::cpu::{
+    assemble_object, assemble_object_for, link, link_for, parse, HackCpu, MachineProfile, Object,
+    RunOutcome,
+};
+use std::collections::HashMap;
+
+/// R15 = R13 * R14 for non-negative R14, returns to the address in R12
+const MULT: &str = "
+(MULT)
+    @R15
+    M=0
+    @R14
+    D=M
+    @count
+    M=D
+(MULT$LOOP)
+    @count
+    D=M
+    @MULT$DONE
+    D;JEQ
+    @R13
+    D=M
+    @R15
+    M=D+M
+    @count
+    M=M-1
+    @MULT$LOOP
+    0;JMP
+(MULT$DONE)
+    @R12
+    A=M
+    0;JMP
+";
+
+/// computes 6 * 7 and 3 * 5 into `result` and R1
+const MAIN: &str = "
+    @6
+    D=A
+    @R13
+    M=D
+    @7
+    D=A
+    @R14
+    M=D
+    @main$ret1
+    D=A
+    @R12
+    M=D
+    @MULT
+    0;JMP
+(main$ret1)
+    @R15
+    D=M
+    @result
+    M=D
+    @count
+    M=D
+    @3
+    D=A
+    @R13
+    M=D
+    @5
+    D=A
+    @R14
+    M=D
+    @main$ret2
+    D=A
+    @R12
+    M=D
+    @MULT
+    0;JMP
+(main$ret2)
+    @R15
+    D=M
+    @R1
+    M=D
+(main$end)
+    @main$end
+    0;JMP
+";
+
+#[test]
+fn objects() {
+    let mult = assemble_object("mult.asm", MULT).unwrap();
+    assert_eq!(mult.exports, HashMap::from([("MULT".to_string(), 0)]));
+    assert_eq!(
+        mult.locals,
+        HashMap::from([("MULT$LOOP".to_string(), 6), ("MULT$DONE".to_string(), 18)])
+    );
+    assert_eq!(mult.relocations, vec![8, 16]);
+    assert_eq!(
+        mult.references,
+        vec![
+            (4, "count".to_string()),
+            (6, "count".to_string()),
+            (14, "count".to_string())
+        ]
+    );
+    assert_eq!(mult.instructions[4], parse("@0").unwrap()[0]);
+    assert_eq!(mult.source_map.len(), mult.instructions.len());
+
+    let main = assemble_object("main.asm", MAIN).unwrap();
+    assert!(main.exports.is_empty());
+    assert_eq!(main.references[0], (12, "MULT".to_string()));
+
+    assert_eq!(
+        assemble_object("bad.asm", "@1\nD=X").unwrap_err(),
+        "bad.asm:2:3: error: expected a computation but got `X`"
+    );
+}
+
+#[test]
+fn linking() {
+    let mult = assemble_object("mult.asm", MULT).unwrap();
+    let main = assemble_object("main.asm", MAIN).unwrap();
+    let linked = link(&[main.clone(), mult.clone()]).unwrap();
+    let program = &linked.program;
+    assert_eq!(program.instructions.len(), 40 + 21);
+    assert_eq!(program.labels["MULT"], 40);
+    assert_eq!(program.labels["MULT$LOOP"], 46);
+    assert_eq!(program.labels["main$end"], 38);
+
+    // every object has its own `count`
+    assert_eq!(linked.sections[0].variables["result"], 16);
+    assert_eq!(linked.sections[0].variables["count"], 17);
+    assert_eq!(linked.sections[1].variables["count"], 18);
+    assert_eq!(
+        program.variables,
+        HashMap::from([("result".to_string(), 16)])
+    );
+    assert_eq!(linked.section_of(50).unwrap().name, "mult.asm");
+    assert_eq!(linked.section_of(61), None);
+
+    let mut cpu = HackCpu::from_program(program);
+    assert!(matches!(cpu.run(10_000), RunOutcome::Halted { .. }));
+    assert_eq!(cpu.ram(16), 42);
+    assert_eq!(cpu.ram(1), 15);
+    assert_eq!(cpu.ram(17), 42);
+
+    // the runtime can come first as well, then it is the entry point
+    let linked = link(&[mult, main]).unwrap();
+    assert_eq!(linked.program.labels["MULT"], 0);
+    assert_eq!(linked.sections[1].start, 21);
+}
+
+#[test]
+fn text_format() {
+    let mult = assemble_object("mult.asm", MULT).unwrap();
+    let text = mult.to_text();
+    assert!(text.starts_with("hack object\nname mult.asm\nexport MULT 0\nlocal MULT$LOOP 6\n"));
+    assert!(text.contains("\nref 4 count\n"));
+    assert_eq!(Object::from_text(&text).unwrap(), mult);
+
+    let mut stripped = mult.clone();
+    stripped.source_map.clear();
+    assert_eq!(Object::from_text(&stripped.to_text()).unwrap(), stripped);
+
+    assert!(Object::from_text("name x").is_err());
+    assert_eq!(
+        Object::from_text("hack object\nreloc x").unwrap_err(),
+        "unexpected `reloc x` in line 2"
+    );
+    assert_eq!(
+        Object::from_text("hack object\nreloc 3\ncode\n0000000000000000").unwrap_err(),
+        "the relocation at 3 is outside of the 1 instructions"
+    );
+}
+
+#[test]
+fn link_errors() {
+    let a = assemble_object("a.asm", "(F)\n@F\n0;JMP").unwrap();
+    let b = assemble_object("b.asm", "(F)\n@F\n0;JMP").unwrap();
+    assert_eq!(
+        link(&[a.clone(), b]).unwrap_err(),
+        "the label F is exported by a.asm and b.asm"
+    );
+
+    let mut big = a.clone();
+    big.exports.clear();
+    big.instructions = parse(&"D=0\n".repeat(0x8000)).unwrap();
+    assert_eq!(
+        link(&[a, big]).unwrap_err(),
+        "the linked program has 32770 instructions but the rom only holds 32768"
+    );
+
+    let names: String = (0..16400).map(|i| format!("@v{}\n", i)).collect();
+    let vars = assemble_object("vars.asm", &names).unwrap();
+    assert_eq!(
+        link(&[vars]).unwrap_err(),
+        "the variables of vars.asm reach into the screen at 16384"
+    );
+}
+
+#[test]
+fn machine_profiles() {
+    let machine = MachineProfile {
+        name: String::from("small"),
+        rom_size: 16,
+        screen: 0x2000,
+        keyboard: 0x4000,
+        statics: 32,
+        ..MachineProfile::hack()
+    };
+    let object = assemble_object_for("a.asm", "@SCREEN\nD=A\n@x\nM=D", &machine).unwrap();
+    assert_eq!(object.instructions[0], parse("@8192").unwrap()[0]);
+    let linked = link_for(std::slice::from_ref(&object), &machine).unwrap();
+    assert_eq!(linked.program.variables["x"], 32);
+    assert_eq!(
+        link(std::slice::from_ref(&object))
+            .unwrap()
+            .program
+            .variables["x"],
+        16
+    );
+
+    let big = assemble_object_for("big.asm", &"D=0\n".repeat(13), &machine).unwrap();
+    assert_eq!(
+        link_for(&[object, big], &machine).unwrap_err(),
+        "the linked program has 17 instructions but the rom only holds 16"
+    );
+
+    let names: String = (0..8161).map(|i| format!("@v{}\n", i)).collect();
+    let machine = MachineProfile {
+        rom_size: 0x8000,
+        ..machine
+    };
+    let vars = assemble_object_for("vars.asm", &names, &machine).unwrap();
+    assert_eq!(
+        link_for(&[vars], &machine).unwrap_err(),
+        "the variables of vars.asm reach into the screen at 8192"
+    );
+
+    // labels past 0x7fff do not fit into an a-instruction
+    let machine = MachineProfile::extended();
+    let pad = assemble_object_for("pad.asm", &"D=0\n".repeat(0x8000), &machine).unwrap();
+    let far = assemble_object_for("far.asm", "(FAR)\n@FAR\n0;JMP", &machine).unwrap();
+    assert_eq!(
+        link_for(&[pad.clone(), far.clone()], &machine).unwrap_err(),
+        "a label of far.asm is at 32768 but a-instructions only reach 32767"
+    );
+    let main = assemble_object_for("main.asm", "@FAR\n0;JMP", &machine).unwrap();
+    assert_eq!(
+        link_for(&[main, pad, far], &machine).unwrap_err(),
+        "FAR of main.asm is at 32770 but a-instructions only reach 32767"
+    );
+}
@@ -0,0 +1,259 @@
+use n2t_lib::cpu::{optimize, parse, parse_program, AsmProgram, HackCpu, Rewrite};
+use std::collections::HashMap;
+use std::fs::read_to_string;
+
+/// optimizes `code`, checks that it computes the same for every `ram` setup and returns the result
+fn check(code: &str, rams: &[&[(usize, i16)]]) -> n2t_lib::cpu::Optimized {
+    let program = parse_program(code).unwrap();
+    let optimized = optimize(&program);
+    for ram in rams {
+        let setup = |cpu: &mut HackCpu| {
+            for &(addr, val) in ram.iter() {
+                cpu.set_ram(addr, val);
+            }
+        };
+        optimized.verify(&program, 1_000_000, setup).unwrap();
+    }
+    optimized
+}
+
+fn text(program: &AsmProgram) -> Vec<String> {
+    program.instructions.iter().map(|i| i.to_string()).collect()
+}
+
+#[test]
+fn redundant_loads_and_no_ops() {
+    let code = "
+        @R1
+        D=M
+        @R1
+        M=D+1
+        D=D
+        @R1
+        M=M
+        @R1
+        M=0
+    (END)
+        @END
+        0;JMP
+    ";
+    let optimized = check(code, &[&[(1, 5)], &[(1, -3)]]);
+    assert_eq!(
+        text(&optimized.program),
+        ["@1", "D=M", "M=D+1", "M=M", "M=0", "@5", "0;JMP"]
+    );
+    assert_eq!(optimized.saved(), 4);
+    assert_eq!(optimized.rewrites[&Rewrite::RedundantLoad], 3);
+    // M=M reads M, which a device could notice
+    assert_eq!(optimized.rewrites[&Rewrite::NoOp], 1);
+    assert_eq!(optimized.program.labels["END"], 5);
+    assert_eq!(optimized.program.relocations, vec![5]);
+}
+
+#[test]
+fn dead_code_and_jump_chains() {
+    let code = "
+        @R0
+        D=M
+        @SKIP
+        D;JEQ
+        @STEP
+        0;JMP
+        @R5
+        M=1
+    (STEP)
+        @MIDDLE
+        0;JMP
+    (MIDDLE)
+        @R6
+        M=1
+    (SKIP)
+        @END
+        0;JMP
+    (END)
+        @END
+        0;JMP
+    ";
+    let optimized = check(code, &[&[(0, 0)], &[(0, 1)]]);
+    assert_eq!(
+        text(&optimized.program),
+        ["@0", "D=M", "@10", "D;JEQ", "@6", "0;JMP", "@6", "M=1", "@10", "0;JMP", "@10", "0;JMP"]
+    );
+    assert_eq!(optimized.rewrites[&Rewrite::JumpChain], 2);
+    assert_eq!(optimized.rewrites[&Rewrite::DeadCode], 4);
+    assert_eq!(optimized.program.labels["SKIP"], 8);
+    assert_eq!(optimized.program.labels["END"], 10);
+
+    // the first instruction kept in place of removed ones keeps its source position
+    let program = parse_program(code).unwrap();
+    assert_eq!(
+        optimized.program.source_map.len(),
+        optimized.program.instructions.len()
+    );
+    assert_eq!(
+        optimized.program.source_pos(6),
+        program.source_pos(program.labels["MIDDLE"])
+    );
+    assert_eq!(optimized.addr_map.len(), program.instructions.len() + 1);
+    assert_eq!(optimized.addr_map[6], 6);
+    assert_eq!(optimized.addr_map[8], 6);
+}
+
+#[test]
+fn conditional_jump_chain_keeps_a() {
+    // after falling through A is read, so the target cannot change
+    let code = "
+        @R0
+        D=M
+        @HOP
+        D;JEQ
+        D=A
+        @R1
+        M=D
+    (HOP)
+        @END
+        0;JMP
+    (END)
+        @END
+        0;JMP
+    ";
+    let optimized = check(code, &[&[(0, 0)], &[(0, 1)]]);
+    assert!(!optimized.rewrites.contains_key(&Rewrite::JumpChain));
+}
+
+#[test]
+fn stack_idioms() {
+    let code = "
+        @SP
+        M=0
+        @256
+        D=A
+        @SP
+        M=D
+        @7
+        D=A
+        @SP
+        A=M
+        M=D
+        @SP
+        M=M+1
+        @SP
+        M=M+1
+        @SP
+        A=M
+        D=M
+        @SP
+        M=M-1
+        @R13
+        M=D
+    (END)
+        @END
+        0;JMP
+    ";
+    let optimized = check(code, &[&[(257, 42)]]);
+    assert_eq!(optimized.rewrites[&Rewrite::StackIdiom], 2);
+    assert_eq!(
+        text(&optimized.program)[8..],
+        [
+            "@0", "AM=M+1", "A=A-1", "M=D", "@0", "M=M+1", "M=M-1", "A=M+1", "D=M", "@13", "M=D",
+            "@19", "0;JMP"
+        ]
+    );
+}
+
+#[test]
+fn dead_stores() {
+    let code = "
+        @R0
+        A=D
+        @R1
+        D=M
+        @R2
+        M=D
+    (END)
+        @END
+        0;JMP
+    ";
+    let optimized = check(code, &[&[(1, 9)]]);
+    assert_eq!(optimized.rewrites[&Rewrite::DeadStore], 1);
+    assert_eq!(optimized.saved(), 1);
+
+    // A=M reads M, which a device could notice
+    let optimized = check(&code.replace("A=D", "A=M"), &[&[(1, 9)]]);
+    assert!(!optimized.rewrites.contains_key(&Rewrite::DeadStore));
+    assert_eq!(text(&optimized.program)[1], "A=M");
+}
+
+/// label references that are not jumps are relocated too
+#[test]
+fn relocations() {
+    let code = "
+        D=D
+        @RET
+        D=A
+        @R15
+        M=D
+        @SUB
+        0;JMP
+    (RET)
+        @R15
+        M=0
+        D=0
+    (END)
+        @END
+        0;JMP
+    (SUB)
+        D=D
+        @R15
+        A=M
+        0;JMP
+    ";
+    let optimized = check(code, &[&[]]);
+    assert_eq!(optimized.saved(), 2);
+    let program = &optimized.program;
+    assert_eq!(program.labels["RET"], 6);
+    assert_eq!(program.instructions[0], parse("@6").unwrap()[0]);
+    assert_eq!(program.relocations, vec![0, 4, 9]);
+}
+
+#[test]
+fn plain_programs() {
+    let max = check(
+        &read_to_string("tests/projects/06/max/Max.asm").unwrap(),
+        &[&[(0, 3), (1, 5)], &[(0, 7), (1, -2)]],
+    );
+    assert_eq!(max.saved(), 0);
+
+    let rect = check(
+        &read_to_string("tests/projects/06/rect/Rect.asm").unwrap(),
+        &[&[(0, 10)]],
+    );
+    assert!(rect.report().starts_with("25 -> "));
+
+    // pong jumps to numeric addresses, which have to be relocated too
+    let pong = parse_program(&read_to_string("tests/projects/06/pong/Pong.asm").unwrap()).unwrap();
+    let optimized = optimize(&pong);
+    let labels: HashMap<&String, usize> = pong
+        .labels
+        .iter()
+        .map(|(label, &addr)| (label, optimized.addr_map[addr]))
+        .collect();
+    for (label, addr) in &optimized.program.labels {
+        assert_eq!(labels[label], *addr);
+    }
+    assert!(optimized.saved() > 0);
+    assert_eq!(
+        optimized.program.source_map.len(),
+        optimized.program.instructions.len()
+    );
+
+    // the optimized program is faster, so the screens only match once the game is over
+    let screen = |program: &AsmProgram| {
+        let mut cpu = HackCpu::from_program(program);
+        cpu.run(20_000_000);
+        cpu.ram_range(16384..24576)
+    };
+    let expected = screen(&pong);
+    assert!(expected.iter().any(|&word| word != 0));
+    assert_eq!(screen(&optimized.program), expected);
+}
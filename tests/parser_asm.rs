@@ -1,4 +1,9 @@
-use n2t_lib::cpu::{asm2ml, ml2asm, parse, str2ml, CPUInstruction, Comp, Dest, Jump};
+use n2t_lib::cpu::{
+    asm2ml, assemble, ml2asm, parse, parse_program, str2ml, CPUInstruction, Comp, Dest, HackCpu,
+    Jump, Severity, SourcePos,
+};
+use std::collections::HashMap;
+use std::fs::read_to_string;
 
 #[test]
 fn jmp() {
@@ -53,8 +58,8 @@ fn test_edge_cases() {
             CPUInstruction::AInstruc(2),
             CPUInstruction::AInstruc(1),
             CPUInstruction::AInstruc(8),
-            CPUInstruction::CInstruc(Comp::DMinusOne, Dest::A, Jump::Null),
-            CPUInstruction::CInstruc(Comp::AMinusOne, Dest::A, Jump::Null)
+            CPUInstruction::CInstruc(Comp::AMinusOne, Dest::A, Jump::Null),
+            CPUInstruction::CInstruc(Comp::DMinusOne, Dest::A, Jump::Null)
         ]
     );
 
@@ -62,3 +67,189 @@ fn test_edge_cases() {
     assert_eq!(ml.clone(), asm2ml(asm.clone()));
     assert_eq!(ml2asm(ml).unwrap(), asm);
 }
+
+#[test]
+fn all_comp_encodings() {
+    for bits in 0..128u16 {
+        let ml = vec![0b111 << 13 | bits << 6 | 0b010 << 3];
+        let asm = ml2asm(ml.clone()).unwrap();
+        assert_eq!(
+            asm,
+            vec![CPUInstruction::CInstruc(
+                Comp::from_bits(bits as u8),
+                Dest::D,
+                Jump::Null
+            )]
+        );
+        assert_eq!(asm2ml(asm), ml);
+    }
+}
+
+#[test]
+fn comp_mnemonics() {
+    let code = r"
+        D=-M
+        D=!M
+        D=M-1
+    ";
+    let asm = parse(code).unwrap();
+    assert_eq!(
+        asm2ml(asm),
+        str2ml("1111110011010000\n1111110001010000\n1111110010010000").unwrap()
+    );
+
+    assert_eq!(Comp::DPulsM.mnemonic(), Some("D+M"));
+    assert_eq!(Comp::from_bits(0b1101010).mnemonic(), None);
+    assert_eq!(format!("{:?}", Comp::AMinusOne), "Comp(A-1)");
+    assert_eq!(format!("{:?}", Comp::from_bits(0b1)), "Comp(0b0000001)");
+}
+
+#[test]
+fn program() {
+    let code =
+        "// sum\n@sum\nM=0\n(LOOP)\n  @idx /* counter */ D=M\n  @LOOP\n\tD;JGT\n(END) @END\n0;JMP\n";
+    let program = parse_program(code).unwrap();
+
+    assert_eq!(program.instructions, parse(code).unwrap());
+    assert_eq!(
+        program.labels,
+        HashMap::from([("LOOP".to_string(), 2), ("END".to_string(), 6)])
+    );
+    assert_eq!(
+        program.variables,
+        HashMap::from([("sum".to_string(), 16), ("idx".to_string(), 17)])
+    );
+
+    let pos = |line, column| SourcePos { line, column };
+    assert_eq!(
+        program.source_map,
+        vec![
+            pos(2, 1),
+            pos(3, 1),
+            pos(5, 3),
+            pos(5, 22),
+            pos(6, 3),
+            pos(7, 2),
+            pos(8, 7),
+            pos(9, 1),
+        ]
+    );
+    assert_eq!(program.source_pos(3), Some(pos(5, 22)));
+    assert_eq!(program.source_pos(8), None);
+    assert_eq!(program.addr_of_line(5), Some(2));
+    assert_eq!(program.labels_at(6), vec!["END"]);
+    assert!(program.labels_at(0).is_empty());
+
+    let cpu = HackCpu::from_program(&program);
+    assert_eq!(cpu.rom(), &program.instructions[..]);
+}
+
+#[test]
+fn program_source_map_pong() {
+    let code = read_to_string("tests/projects/06/pong/Pong.asm").unwrap();
+    let program = parse_program(&code).unwrap();
+    assert_eq!(program.source_map.len(), program.instructions.len());
+
+    // every instruction of pong is on its own line
+    let lines: Vec<&str> = code.lines().collect();
+    for (addr, pos) in program.source_map.iter().enumerate() {
+        let line = lines[pos.line - 1].trim();
+        assert!(
+            !line.starts_with('(') && !line.is_empty(),
+            "{}: {}",
+            addr,
+            line
+        );
+    }
+    assert!(program.source_map.windows(2).all(|w| w[0] < w[1]));
+}
+
+#[test]
+fn errors() {
+    let code = "@abc\n0=D\nD=M;JXX\nD=Q\nM=M+1\n(abc)\n(abc)\nD=\n";
+    let assembly = assemble(code);
+    assert!(assembly.program.is_none());
+    assert!(assembly.has_errors());
+
+    let errors: Vec<String> = assembly.errors().map(|e| e.to_string()).collect();
+    assert_eq!(
+        errors,
+        vec![
+            "2:1: error: expected a destination but got `0`",
+            "3:5: error: expected a jump but got `JXX`",
+            "4:3: error: expected a computation but got `Q`",
+            "7:1: error: the label abc has already been set to 5",
+            "8:3: error: expected a computation but got the end of the file",
+        ]
+    );
+    let first = assembly.errors().next().unwrap();
+    assert_eq!(first.span, 5..6);
+    assert_eq!(first.pos, SourcePos { line: 2, column: 1 });
+
+    let err = parse(code).unwrap_err();
+    assert_eq!(
+        err,
+        parse_program(code).unwrap_err(),
+        "parse fails with the first error"
+    );
+}
+
+#[test]
+fn warnings() {
+    let code = r"
+        @40000
+        D=A
+        @counter
+        M=D
+        @countr
+        M=D-1
+        @counter
+        D=M
+    (SCREEN)
+        @SCREEN
+        0;JMP
+    (UNUSED)
+    ";
+    let assembly = assemble(code);
+    assert!(!assembly.has_errors());
+    assert!(assembly
+        .diagnostics
+        .iter()
+        .all(|d| d.severity == Severity::Warning));
+
+    let warnings: Vec<String> = assembly.warnings().map(|w| w.to_string()).collect();
+    assert_eq!(
+        warnings,
+        vec![
+            "2:9: warning: the constant 40000 does not fit into 15 bits and is truncated to 7232",
+            "6:9: warning: the variable countr is only used once, is it a typo?",
+            "10:5: warning: the label SCREEN shadows the predefined symbol SCREEN = 16384",
+            "13:5: warning: the label UNUSED is never used",
+        ]
+    );
+
+    let program = assembly.program.unwrap();
+    assert_eq!(program.instructions[8], CPUInstruction::AInstruc(8));
+    assert_eq!(program, parse_program(code).unwrap());
+}
+
+#[test]
+fn rom_overflow() {
+    let code = "D=D+1\n".repeat(0x8001);
+    let assembly = assemble(&code);
+    let warnings: Vec<String> = assembly.warnings().map(|w| w.to_string()).collect();
+    assert_eq!(
+        warnings,
+        vec!["32769:1: warning: the program has 32769 instructions but the rom only holds 32768"]
+    );
+    assert_eq!(assembly.program.unwrap().instructions.len(), 0x8001);
+}
+
+#[test]
+fn pong_has_no_errors() {
+    let assembly = assemble(&read_to_string("tests/projects/06/pong/Pong.asm").unwrap());
+    assert!(!assembly.has_errors());
+    assert!(assembly
+        .warnings()
+        .all(|w| w.message.ends_with("is never used")));
+}
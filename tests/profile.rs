@@ -0,0 +1,97 @@
+use n2t_lib::cpu::{parse_with_labels, HackCpu, HotLoop, LabelTotal, RunOutcome};
+use std::fs::read_to_string;
+
+const COUNTDOWN: &str = r"
+        @10
+        D=A
+        @cnt
+        M=D
+    (LOOP)
+        @cnt
+        MD=M-1
+        @LOOP
+        D;JGT
+    (END)
+        @END
+        0;JMP
+    ";
+
+#[test]
+fn counts() {
+    let (rom, labels) = parse_with_labels(COUNTDOWN).unwrap();
+    let mut cpu = HackCpu::new(rom);
+    cpu.enable_profile();
+    assert_eq!(cpu.run(1000), RunOutcome::Halted { cycles: 45 });
+
+    let profile = cpu.profile().unwrap();
+    assert_eq!(profile.counts(), &[1, 1, 1, 1, 10, 10, 10, 10, 1, 0]);
+    assert_eq!(profile.total(), 45);
+    assert_eq!(profile.count(100), 0);
+
+    assert_eq!(
+        profile.label_totals(&labels),
+        vec![
+            LabelTotal {
+                label: "LOOP".to_string(),
+                start: 4,
+                end: 8,
+                cycles: 40
+            },
+            LabelTotal {
+                label: "".to_string(),
+                start: 0,
+                end: 4,
+                cycles: 4
+            },
+            LabelTotal {
+                label: "END".to_string(),
+                start: 8,
+                end: 10,
+                cycles: 1
+            },
+        ]
+    );
+    assert_eq!(
+        profile.hot_loops(&labels),
+        vec![HotLoop {
+            start: 4,
+            end: 7,
+            label: Some("LOOP".to_string()),
+            iterations: 9,
+            cycles: 40
+        }]
+    );
+
+    let mut profile = cpu.disable_profile().unwrap();
+    assert!(cpu.profile().is_none());
+    profile.clear();
+    assert_eq!(profile.total(), 0);
+}
+
+#[test]
+fn pong() {
+    let (rom, labels) =
+        parse_with_labels(&read_to_string("tests/projects/06/pong/Pong.asm").unwrap()).unwrap();
+    let mut cpu = HackCpu::new(rom);
+    cpu.enable_profile();
+    cpu.run(1_000_000);
+
+    let profile = cpu.profile().unwrap();
+    assert_eq!(profile.total(), 1_000_000);
+    let labels_total: usize = profile.label_totals(&labels).iter().map(|l| l.cycles).sum();
+    assert_eq!(labels_total, 1_000_000);
+    let functions = profile.function_totals(&labels);
+    assert!(functions
+        .iter()
+        .all(|(function, _)| !function.contains('$')));
+    assert_eq!(functions.iter().map(|(_, c)| c).sum::<usize>(), 1_000_000);
+    assert!(!profile.hot_loops(&labels).is_empty());
+
+    let report = profile.report(&labels, 5);
+    assert!(report.starts_with("1000000 instructions executed\n\nhot loops\n"));
+    assert!(report.contains("\nfunctions\n"));
+    assert!(report.contains("\nlabels\n"));
+    let (top_function, cycles) = &functions[0];
+    assert!(report.contains(&format!("{:>12}", cycles)));
+    assert!(report.contains(&format!("%  {}\n", top_function)));
+}
@@ -0,0 +1,67 @@
+use n2t_lib::cpu::{parse, AccessKind, HackCpu, MemoryAccess, RunOutcome, Trace};
+
+#[test]
+fn ring_buffer() {
+    let code = r"
+        @R0
+        M=1
+    (LOOP)
+        @R0
+        M=M+1
+        @LOOP
+        0;JMP
+    ";
+    let mut cpu = HackCpu::new(parse(code).unwrap());
+    cpu.enable_trace(3);
+    assert_eq!(cpu.run(10), RunOutcome::BudgetExhausted { cycles: 10 });
+
+    let trace = cpu.trace().unwrap();
+    assert_eq!(trace.len(), 3);
+    assert_eq!(trace.capacity(), 3);
+    let cycles: Vec<usize> = trace.entries().map(|e| e.cycle).collect();
+    assert_eq!(cycles, vec![7, 8, 9]);
+    let pcs: Vec<usize> = trace.entries().map(|e| e.pc).collect();
+    assert_eq!(pcs, vec![3, 4, 5]);
+
+    let last = trace.last().unwrap();
+    assert_eq!(last.a_reg, 2);
+    assert_eq!(
+        trace.entries().next().unwrap().write,
+        Some(MemoryAccess {
+            addr: 0,
+            kind: AccessKind::Write,
+            old: 2,
+            new: 3
+        })
+    );
+
+    let mut trace = cpu.disable_trace().unwrap();
+    cpu.run(10);
+    assert!(cpu.trace().is_none());
+    trace.clear();
+    assert!(trace.is_empty());
+}
+
+#[test]
+fn export() {
+    let mut cpu = HackCpu::new(parse("@5\nD=A\n@R1\nM=D").unwrap());
+    cpu.enable_trace(100);
+    cpu.run(100);
+    let trace = cpu.trace().unwrap();
+
+    assert_eq!(
+        trace.to_csv(),
+        "cycle,pc,instruction,a,d,addr,old,new\n\
+         0,0,0000000000000101,5,0,,,\n\
+         1,1,1110110000010000,5,5,,,\n\
+         2,2,0000000000000001,1,5,,,\n\
+         3,3,1110001100001000,1,5,1,0,5\n"
+    );
+
+    let text = trace.to_text();
+    assert_eq!(text.lines().count(), 4);
+    assert!(text.lines().last().unwrap().ends_with("RAM[1]: 0 -> 5"));
+    assert!(text.lines().nth(1).unwrap().contains(" D=A "));
+
+    assert!(Trace::new(0).is_empty());
+}